@@ -0,0 +1,16 @@
+use solana_program::{
+    account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, program_error::PrintProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::{error::StakingError, processor};
+
+entrypoint!(process_instruction);
+fn process_instruction(program_id: &Pubkey, accounts: &[AccountInfo], instruction_data: &[u8]) -> ProgramResult {
+    if let Err(error) = processor::process_instruction(program_id, accounts, instruction_data) {
+        // log the readable message of our own errors
+        error.print::<StakingError>();
+        return Err(error);
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use num_derive::FromPrimitive;
+use solana_program::{
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+};
+use thiserror::Error;
+
+// Codes are part of the client interface, append new variants and never renumber
+#[derive(Clone, Copy, Debug, Eq, Error, FromPrimitive, PartialEq)]
+pub enum StakingError {
+    #[error("signer is not the vault admin, the pending admin or the staker")]
+    Unauthorized = 0,
+    #[error("vault account does not match the vault PDA")]
+    WrongVault = 1,
+    #[error("vault data can't be deserialized")]
+    InvalidVaultData = 2,
+    #[error("vault already exists")]
+    VaultAlreadyExists = 3,
+    #[error("vault is paused")]
+    VaultPaused = 4,
+    #[error("no admin handover is pending")]
+    NoPendingAdmin = 5,
+    #[error("wrong token program")]
+    WrongTokenProgram = 6,
+    #[error("reward mint is not a token mint")]
+    InvalidRewardMint = 7,
+    #[error("reward mint does not match the vault")]
+    WrongRewardMint = 8,
+    #[error("wrong payer reward token account")]
+    WrongPayerRewardHolder = 9,
+    #[error("wrong vault reward token account")]
+    WrongVaultRewardHolder = 10,
+    #[error("wrong payer nft token account")]
+    WrongPayerNftHolder = 11,
+    #[error("wrong vault nft token account")]
+    WrongVaultNftHolder = 12,
+    #[error("metadata account does not match the nft")]
+    WrongMetadata = 13,
+    #[error("first creator of the nft is not verified")]
+    CreatorNotVerified = 14,
+    #[error("whitelist account does not match the whitelist PDA")]
+    WrongWhitelist = 15,
+    #[error("collection is not whitelisted")]
+    NotWhitelisted = 16,
+    #[error("whitelist data can't be deserialized")]
+    InvalidRateData = 17,
+    #[error("collection is disabled for new stakes")]
+    WhitelistDisabled = 18,
+    #[error("stake account does not match the stake PDA")]
+    WrongStakeData = 19,
+    #[error("stake data can't be deserialized")]
+    InvalidStakeData = 20,
+    #[error("stake is not active")]
+    StakeInactive = 21,
+    #[error("nft is staked in another vault")]
+    WrongStakeVault = 22,
+    #[error("minimum staking period not reached")]
+    MinPeriodNotReached = 23,
+    #[error("instruction data can't be deserialized")]
+    InvalidInstruction = 24,
+    #[error("metadata can't be deserialized")]
+    InvalidMetadata = 25,
+    #[error("nft has no creators")]
+    NoCreators = 26,
+    #[error("arithmetic overflow")]
+    MathOverflow = 27,
+    #[error("reward period must be greater than zero")]
+    InvalidRewardPeriod = 28,
+    #[error("account belongs to another account type")]
+    WrongAccountType = 29,
+    #[error("account is on the deployed program's layout, unstake its nfts with UnstakeLegacy")]
+    LegacyAccount = 30,
+    #[error("account is not on the deployed program's layout")]
+    NotLegacyAccount = 31,
+    #[error("collection of the nft is not verified")]
+    CollectionNotVerified = 32,
+    #[error("mint is not a token mint")]
+    InvalidMint = 33,
+    #[error("mint is not an nft, supply must be 1 and decimals 0")]
+    NotAnNft = 34,
+    #[error("master edition account does not match the nft")]
+    WrongMasterEdition = 35,
+    #[error("stats account does not match the stats PDA")]
+    WrongStats = 36,
+    #[error("staker stats account does not match the staker PDA")]
+    WrongStakerStats = 37,
+    #[error("stats data can't be deserialized")]
+    InvalidStatsData = 38,
+    #[error("batch needs at least one nft and whole groups of nft accounts")]
+    InvalidNftAccounts = 39,
+    #[error("token metadata program account does not match")]
+    WrongMetadataProgram = 40,
+    #[error("lock tier does not exist in the vault")]
+    InvalidLockTier = 41,
+    #[error("stake is still locked")]
+    LockNotExpired = 42,
+    #[error("early exit penalty can't exceed 10000 bps")]
+    InvalidPenalty = 43,
+    #[error("boost account does not match the boost PDA")]
+    WrongBoost = 44,
+    #[error("boost data can't be deserialized")]
+    InvalidBoostData = 45,
+    #[error("boost has to be between 1 and 50000 bps")]
+    InvalidBoost = 46,
+    #[error("the deployed program's layout has no room for this change")]
+    LegacyLayoutTooSmall = 47,
+    #[error("account layout version is not supported")]
+    UnsupportedVersion = 48,
+}
+
+impl From<StakingError> for ProgramError {
+    fn from(e: StakingError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for StakingError {
+    fn type_of() -> &'static str {
+        "StakingError"
+    }
+}
+
+impl PrintProgramError for StakingError {
+    fn print<E>(&self) {
+        msg!("Error: {}", self);
+    }
+}
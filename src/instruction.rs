@@ -0,0 +1,792 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::state::{
+    find_boost_address, find_master_edition_address, find_metadata_address, find_stake_address,
+    find_staker_stats_address, find_stats_address, find_vault_address, find_whitelist_address, legacy_reward_mint,
+    LockTier, RewardMode, VerificationMode, LEGACY_VAULT_ID, MAX_LOCK_TIERS,
+};
+
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub enum StakeInstruction {
+    /// Creates a vault, the payer becomes its admin.
+    ///
+    /// 0. `[signer, writable]` payer
+    /// 1. `[]` system program
+    /// 2. `[writable]` vault PDA
+    /// 3. `[]` rent sysvar
+    /// 4. `[]` reward mint
+    GenerateVault {
+        vault_id: u64,
+        min_period: u64,
+        reward_period: u64,
+        reward_mode: RewardMode,
+    },
+
+    /// 0. `[signer, writable]` staker
+    /// 1. `[]` nft mint
+    /// 2. `[]` nft metadata
+    /// 3. `[]` vault PDA
+    /// 4. `[writable]` staker nft ATA
+    /// 5. `[writable]` vault nft ATA
+    /// 6. `[]` token program
+    /// 7. `[]` system program
+    /// 8. `[]` rent sysvar
+    /// 9. `[]` associated token account program
+    /// 10. `[writable]` stake PDA
+    /// 11. `[]` whitelist PDA
+    /// 12. `[]` nft master edition
+    /// 13. `[writable]` vault stats PDA
+    /// 14. `[]` boost PDA, may be uninitialized
+    /// 15. `[writable]` staker stats PDA, optional
+    Stake {
+        // index into the vault's lock tiers, `None` stakes without a lock
+        lock_tier: Option<u8>,
+    },
+
+    /// 0. `[signer, writable]` staker
+    /// 1. `[]` system program
+    /// 2. `[]` nft mint
+    /// 3. `[]` token program
+    /// 4. `[]` rent sysvar
+    /// 5. `[]` associated token account program
+    /// 6. `[writable]` stake PDA
+    /// 7. `[]` vault PDA
+    /// 8. `[writable]` staker reward ATA
+    /// 9. `[writable]` vault reward ATA
+    /// 10. `[writable]` staker nft ATA
+    /// 11. `[writable]` vault nft ATA
+    /// 12. `[]` nft metadata
+    /// 13. `[]` whitelist PDA
+    /// 14. `[]` reward mint
+    /// 15. `[writable]` vault stats PDA
+    /// 16. `[]` nft master edition
+    /// 17. `[]` token metadata program
+    /// 18. `[]` boost PDA, optional, may be uninitialized
+    /// 19. `[writable]` staker stats PDA, optional, needs the boost PDA before it
+    Unstake,
+
+    /// Whitelists a collection by its first creator or collection mint, or reprices it.
+    /// The mode decides which of the two the whitelist PDA is keyed by.
+    ///
+    /// 0. `[signer, writable]` admin
+    /// 1. `[]` first creator or collection mint, see the whitelist mode
+    /// 2. `[writable]` whitelist PDA
+    /// 3. `[]` system program
+    /// 4. `[]` rent sysvar
+    /// 5. `[]` vault PDA
+    AddToWhitelist {
+        price: u64,
+        mode: VerificationMode,
+    },
+
+    /// Withdraws reward tokens from the vault.
+    ///
+    /// 0. `[signer, writable]` admin
+    /// 1. `[writable]` admin reward ATA
+    /// 2. `[writable]` vault reward ATA
+    /// 3. `[]` vault PDA
+    /// 4. `[]` reward mint
+    /// 5. `[]` system program
+    /// 6. `[]` token program
+    /// 7. `[]` rent sysvar
+    /// 8. `[]` associated token account program
+    Withdraw {
+        amount: u64,
+    },
+
+    /// Same as ProposeAdmin, the new admin still has to accept. Kept so older clients keep their
+    /// instruction numbering.
+    ///
+    /// 0. `[signer]` admin
+    /// 1. `[writable]` vault PDA
+    SetAdmin {
+        new_admin: Pubkey,
+    },
+
+    /// Proposing the default pubkey cancels a pending handover.
+    ///
+    /// 0. `[signer]` admin
+    /// 1. `[writable]` vault PDA
+    ProposeAdmin {
+        new_admin: Pubkey,
+    },
+
+    /// 0. `[signer]` pending admin
+    /// 1. `[writable]` vault PDA
+    AcceptAdmin,
+
+    /// Pays accrued rewards, the nft stays staked. With early exit enabled only once min_period has passed.
+    /// Passing the boost PDA moves the stake to the mint's current boost from now on.
+    ///
+    /// 0. `[signer, writable]` staker
+    /// 1. `[]` system program
+    /// 2. `[]` nft mint
+    /// 3. `[]` token program
+    /// 4. `[]` rent sysvar
+    /// 5. `[]` associated token account program
+    /// 6. `[writable]` stake PDA
+    /// 7. `[]` vault PDA
+    /// 8. `[writable]` staker reward ATA
+    /// 9. `[writable]` vault reward ATA
+    /// 10. `[]` nft metadata
+    /// 11. `[]` whitelist PDA
+    /// 12. `[]` reward mint
+    /// 13. `[writable]` vault stats PDA
+    /// 14. `[]` boost PDA, optional, may be uninitialized
+    /// 15. `[writable]` staker stats PDA, optional, needs the boost PDA before it
+    Claim,
+
+    /// Running stakes are settled at the old rate up to this point.
+    ///
+    /// 0. `[signer]` admin
+    /// 1. `[writable]` vault PDA
+    UpdateVaultConfig {
+        min_period: u64,
+        reward_period: u64,
+        reward_mode: RewardMode,
+    },
+
+    /// Closes the whitelist PDA, rent goes back to the admin.
+    ///
+    /// 0. `[signer, writable]` admin
+    /// 1. `[]` first creator or collection mint, see the whitelist mode
+    /// 2. `[writable]` whitelist PDA
+    /// 3. `[]` vault PDA
+    RemoveFromWhitelist,
+
+    /// 0. `[signer]` admin
+    /// 1. `[]` first creator or collection mint, see the whitelist mode
+    /// 2. `[writable]` whitelist PDA
+    /// 3. `[]` vault PDA
+    DisableWhitelist {
+        disabled: bool,
+    },
+
+    /// 0. `[signer]` admin
+    /// 1. `[writable]` vault PDA
+    SetPaused {
+        paused: bool,
+    },
+
+    /// Returns the nft without paying rewards.
+    /// min_period and a lock still hold unless the vault is paused.
+    ///
+    /// 0. `[signer, writable]` staker
+    /// 1. `[]` system program
+    /// 2. `[]` nft mint
+    /// 3. `[]` token program
+    /// 4. `[]` rent sysvar
+    /// 5. `[]` associated token account program
+    /// 6. `[writable]` stake PDA
+    /// 7. `[]` vault PDA
+    /// 8. `[writable]` staker nft ATA
+    /// 9. `[writable]` vault nft ATA
+    /// 10. `[writable]` vault stats PDA
+    /// 11. `[]` nft master edition
+    /// 12. `[]` token metadata program
+    /// 13. `[writable]` staker stats PDA, optional
+    EmergencyUnstake,
+
+    /// Stakes several nfts of the same staker into one vault.
+    ///
+    /// 0. `[signer, writable]` staker
+    /// 1. `[]` vault PDA
+    /// 2. `[]` token program
+    /// 3. `[]` system program
+    /// 4. `[]` rent sysvar
+    /// 5. `[]` associated token account program
+    /// 6. `[writable]` vault stats PDA
+    ///
+    /// Then for each nft:
+    ///
+    /// 0. `[]` nft mint
+    /// 1. `[]` nft metadata
+    /// 2. `[]` nft master edition
+    /// 3. `[writable]` staker nft ATA
+    /// 4. `[writable]` vault nft ATA
+    /// 5. `[writable]` stake PDA
+    /// 6. `[]` whitelist PDA
+    /// 7. `[]` boost PDA, may be uninitialized
+    ///
+    /// Then optionally the `[writable]` staker stats PDA.
+    StakeMany {
+        // applies to every nft of the batch
+        lock_tier: Option<u8>,
+    },
+
+    /// Unstakes several nfts of the same staker, the rewards are paid in one transfer.
+    ///
+    /// 0. `[signer, writable]` staker
+    /// 1. `[]` vault PDA
+    /// 2. `[]` token program
+    /// 3. `[]` system program
+    /// 4. `[]` rent sysvar
+    /// 5. `[]` associated token account program
+    /// 6. `[writable]` vault stats PDA
+    /// 7. `[writable]` staker reward ATA
+    /// 8. `[writable]` vault reward ATA
+    /// 9. `[]` reward mint
+    /// 10. `[]` token metadata program
+    ///
+    /// Then for each nft:
+    ///
+    /// 0. `[]` nft mint
+    /// 1. `[]` nft metadata
+    /// 2. `[]` nft master edition
+    /// 3. `[writable]` staker nft ATA
+    /// 4. `[writable]` vault nft ATA
+    /// 5. `[writable]` stake PDA
+    /// 6. `[]` whitelist PDA
+    /// 7. `[]` boost PDA, may be uninitialized
+    ///
+    /// Then optionally the `[writable]` staker stats PDA.
+    UnstakeMany,
+
+    /// Stakes without moving the nft, the vault PDA becomes its delegate and freezes the staker's ATA
+    /// through the token metadata program. Unstake thaws the ATA and revokes the delegate.
+    ///
+    /// 0. `[signer, writable]` staker
+    /// 1. `[]` nft mint
+    /// 2. `[]` nft metadata
+    /// 3. `[]` vault PDA
+    /// 4. `[writable]` staker nft ATA
+    /// 5. `[]` token program
+    /// 6. `[]` system program
+    /// 7. `[]` rent sysvar
+    /// 8. `[writable]` stake PDA
+    /// 9. `[]` whitelist PDA
+    /// 10. `[]` nft master edition
+    /// 11. `[]` token metadata program
+    /// 12. `[writable]` vault stats PDA
+    /// 13. `[]` boost PDA, may be uninitialized
+    /// 14. `[writable]` staker stats PDA, optional
+    StakeInWallet {
+        lock_tier: Option<u8>,
+    },
+
+    /// Replaces the vault's lock tiers, running stakes keep the lock they were staked with.
+    ///
+    /// 0. `[signer]` admin
+    /// 1. `[writable]` vault PDA
+    SetLockTiers {
+        lock_tiers: [LockTier; MAX_LOCK_TIERS],
+    },
+
+    /// Allows Unstake before min_period, `penalty_bps` of the reward is withheld and stays in the vault.
+    ///
+    /// 0. `[signer]` admin
+    /// 1. `[writable]` vault PDA
+    SetEarlyExit {
+        enabled: bool,
+        penalty_bps: u16,
+    },
+
+    /// Creates or updates the boost PDAs of several mints, each multiplies the whitelist price of that mint.
+    /// Stake copies the boost, running stakes keep the one they were staked with. 10000 bps is the plain
+    /// price, the boost has to be between 1 and MAX_BOOST_BPS.
+    ///
+    /// 0. `[signer, writable]` admin
+    /// 1. `[]` vault PDA
+    /// 2. `[]` system program
+    /// 3. `[]` rent sysvar
+    ///
+    /// Then for each entry of `multipliers_bps`:
+    ///
+    /// 0. `[]` nft mint
+    /// 1. `[writable]` boost PDA
+    SetBoosts {
+        multipliers_bps: Vec<u16>,
+    },
+
+    /// Unstakes an nft staked with the deployed program, its stake is still in the 41 byte layout.
+    /// Pays whole reward periods since the stake like that program did and closes the stake, rent goes
+    /// back to the staker. The stats are left alone.
+    ///
+    /// 0. `[signer, writable]` staker
+    /// 1. `[]` system program
+    /// 2. `[]` nft mint
+    /// 3. `[]` token program
+    /// 4. `[]` rent sysvar
+    /// 5. `[]` associated token account program
+    /// 6. `[writable]` stake PDA
+    /// 7. `[]` legacy vault PDA
+    /// 8. `[writable]` staker reward ATA
+    /// 9. `[writable]` vault reward ATA
+    /// 10. `[writable]` staker nft ATA
+    /// 11. `[writable]` vault nft ATA
+    /// 12. `[]` nft metadata
+    /// 13. `[]` whitelist PDA
+    /// 14. `[]` reward mint
+    UnstakeLegacy,
+}
+
+pub fn generate_vault(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    reward_mint: &Pubkey,
+    vault_id: u64,
+    min_period: u64,
+    reward_period: u64,
+    reward_mode: RewardMode,
+) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, vault_id);
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakeInstruction::GenerateVault {
+            vault_id,
+            min_period,
+            reward_period,
+            reward_mode,
+        },
+        vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(vault, false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(*reward_mint, false),
+        ],
+    )
+}
+
+/// `whitelist_key` is the first creator or collection mint the collection was whitelisted with.
+pub fn stake(
+    program_id: &Pubkey,
+    staker: &Pubkey,
+    nft_mint: &Pubkey,
+    vault_id: u64,
+    whitelist_key: &Pubkey,
+    lock_tier: Option<u8>,
+) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, vault_id);
+    let (stake, _) = find_stake_address(program_id, nft_mint);
+    let (whitelist, _) = find_whitelist_address(program_id, &vault, whitelist_key);
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakeInstruction::Stake { lock_tier },
+        vec![
+            AccountMeta::new(*staker, true),
+            AccountMeta::new_readonly(*nft_mint, false),
+            AccountMeta::new_readonly(find_metadata_address(nft_mint), false),
+            AccountMeta::new_readonly(vault, false),
+            AccountMeta::new(get_associated_token_address(staker, nft_mint), false),
+            AccountMeta::new(get_associated_token_address(&vault, nft_mint), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+            AccountMeta::new(stake, false),
+            AccountMeta::new_readonly(whitelist, false),
+            AccountMeta::new_readonly(find_master_edition_address(nft_mint), false),
+            AccountMeta::new(find_stats_address(program_id, &vault).0, false),
+            AccountMeta::new_readonly(find_boost_address(program_id, &vault, nft_mint).0, false),
+            AccountMeta::new(find_staker_stats_address(program_id, &vault, staker).0, false),
+        ],
+    )
+}
+
+pub fn unstake(
+    program_id: &Pubkey,
+    staker: &Pubkey,
+    nft_mint: &Pubkey,
+    vault_id: u64,
+    whitelist_key: &Pubkey,
+    reward_mint: &Pubkey,
+) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, vault_id);
+    let (stake, _) = find_stake_address(program_id, nft_mint);
+    let (whitelist, _) = find_whitelist_address(program_id, &vault, whitelist_key);
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakeInstruction::Unstake,
+        vec![
+            AccountMeta::new(*staker, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(*nft_mint, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+            AccountMeta::new(stake, false),
+            AccountMeta::new_readonly(vault, false),
+            AccountMeta::new(get_associated_token_address(staker, reward_mint), false),
+            AccountMeta::new(get_associated_token_address(&vault, reward_mint), false),
+            AccountMeta::new(get_associated_token_address(staker, nft_mint), false),
+            AccountMeta::new(get_associated_token_address(&vault, nft_mint), false),
+            AccountMeta::new_readonly(find_metadata_address(nft_mint), false),
+            AccountMeta::new_readonly(whitelist, false),
+            AccountMeta::new_readonly(*reward_mint, false),
+            AccountMeta::new(find_stats_address(program_id, &vault).0, false),
+            AccountMeta::new_readonly(find_master_edition_address(nft_mint), false),
+            AccountMeta::new_readonly(spl_token_metadata::id(), false),
+            AccountMeta::new_readonly(find_boost_address(program_id, &vault, nft_mint).0, false),
+            AccountMeta::new(find_staker_stats_address(program_id, &vault, staker).0, false),
+        ],
+    )
+}
+
+pub fn claim(
+    program_id: &Pubkey,
+    staker: &Pubkey,
+    nft_mint: &Pubkey,
+    vault_id: u64,
+    whitelist_key: &Pubkey,
+    reward_mint: &Pubkey,
+) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, vault_id);
+    let (stake, _) = find_stake_address(program_id, nft_mint);
+    let (whitelist, _) = find_whitelist_address(program_id, &vault, whitelist_key);
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakeInstruction::Claim,
+        vec![
+            AccountMeta::new(*staker, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(*nft_mint, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+            AccountMeta::new(stake, false),
+            AccountMeta::new_readonly(vault, false),
+            AccountMeta::new(get_associated_token_address(staker, reward_mint), false),
+            AccountMeta::new(get_associated_token_address(&vault, reward_mint), false),
+            AccountMeta::new_readonly(find_metadata_address(nft_mint), false),
+            AccountMeta::new_readonly(whitelist, false),
+            AccountMeta::new_readonly(*reward_mint, false),
+            AccountMeta::new(find_stats_address(program_id, &vault).0, false),
+            AccountMeta::new_readonly(find_boost_address(program_id, &vault, nft_mint).0, false),
+            AccountMeta::new(find_staker_stats_address(program_id, &vault, staker).0, false),
+        ],
+    )
+}
+
+pub fn emergency_unstake(program_id: &Pubkey, staker: &Pubkey, nft_mint: &Pubkey, vault_id: u64) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, vault_id);
+    let (stake, _) = find_stake_address(program_id, nft_mint);
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakeInstruction::EmergencyUnstake,
+        vec![
+            AccountMeta::new(*staker, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(*nft_mint, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+            AccountMeta::new(stake, false),
+            AccountMeta::new_readonly(vault, false),
+            AccountMeta::new(get_associated_token_address(staker, nft_mint), false),
+            AccountMeta::new(get_associated_token_address(&vault, nft_mint), false),
+            AccountMeta::new(find_stats_address(program_id, &vault).0, false),
+            AccountMeta::new_readonly(find_master_edition_address(nft_mint), false),
+            AccountMeta::new_readonly(spl_token_metadata::id(), false),
+            AccountMeta::new(find_staker_stats_address(program_id, &vault, staker).0, false),
+        ],
+    )
+}
+
+/// Same accounts as `stake` minus the vault nft ATA and associated token program, see `StakeInWallet`.
+pub fn stake_in_wallet(
+    program_id: &Pubkey,
+    staker: &Pubkey,
+    nft_mint: &Pubkey,
+    vault_id: u64,
+    whitelist_key: &Pubkey,
+    lock_tier: Option<u8>,
+) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, vault_id);
+    let (stake, _) = find_stake_address(program_id, nft_mint);
+    let (whitelist, _) = find_whitelist_address(program_id, &vault, whitelist_key);
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakeInstruction::StakeInWallet { lock_tier },
+        vec![
+            AccountMeta::new(*staker, true),
+            AccountMeta::new_readonly(*nft_mint, false),
+            AccountMeta::new_readonly(find_metadata_address(nft_mint), false),
+            AccountMeta::new_readonly(vault, false),
+            AccountMeta::new(get_associated_token_address(staker, nft_mint), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new(stake, false),
+            AccountMeta::new_readonly(whitelist, false),
+            AccountMeta::new_readonly(find_master_edition_address(nft_mint), false),
+            AccountMeta::new_readonly(spl_token_metadata::id(), false),
+            AccountMeta::new(find_stats_address(program_id, &vault).0, false),
+            AccountMeta::new_readonly(find_boost_address(program_id, &vault, nft_mint).0, false),
+            AccountMeta::new(find_staker_stats_address(program_id, &vault, staker).0, false),
+        ],
+    )
+}
+
+pub fn add_to_whitelist(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    vault_id: u64,
+    whitelist_key: &Pubkey,
+    price: u64,
+    mode: VerificationMode,
+) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, vault_id);
+    let (whitelist, _) = find_whitelist_address(program_id, &vault, whitelist_key);
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakeInstruction::AddToWhitelist { price, mode },
+        vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new_readonly(*whitelist_key, false),
+            AccountMeta::new(whitelist, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(vault, false),
+        ],
+    )
+}
+
+pub fn withdraw(program_id: &Pubkey, admin: &Pubkey, vault_id: u64, reward_mint: &Pubkey, amount: u64) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, vault_id);
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakeInstruction::Withdraw { amount },
+        vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new(get_associated_token_address(admin, reward_mint), false),
+            AccountMeta::new(get_associated_token_address(&vault, reward_mint), false),
+            AccountMeta::new_readonly(vault, false),
+            AccountMeta::new_readonly(*reward_mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+        ],
+    )
+}
+
+// Admin instructions that only touch the vault
+fn vault_admin_instruction(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    vault_id: u64,
+    instruction: &StakeInstruction,
+) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, vault_id);
+    Instruction::new_with_borsh(
+        *program_id,
+        instruction,
+        vec![AccountMeta::new_readonly(*admin, true), AccountMeta::new(vault, false)],
+    )
+}
+
+pub fn propose_admin(program_id: &Pubkey, admin: &Pubkey, vault_id: u64, new_admin: &Pubkey) -> Instruction {
+    let new_admin = *new_admin;
+    vault_admin_instruction(program_id, admin, vault_id, &StakeInstruction::ProposeAdmin { new_admin })
+}
+
+pub fn accept_admin(program_id: &Pubkey, pending_admin: &Pubkey, vault_id: u64) -> Instruction {
+    vault_admin_instruction(program_id, pending_admin, vault_id, &StakeInstruction::AcceptAdmin)
+}
+
+pub fn update_vault_config(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    vault_id: u64,
+    min_period: u64,
+    reward_period: u64,
+    reward_mode: RewardMode,
+) -> Instruction {
+    let instruction = StakeInstruction::UpdateVaultConfig {
+        min_period,
+        reward_period,
+        reward_mode,
+    };
+    vault_admin_instruction(program_id, admin, vault_id, &instruction)
+}
+
+pub fn set_paused(program_id: &Pubkey, admin: &Pubkey, vault_id: u64, paused: bool) -> Instruction {
+    vault_admin_instruction(program_id, admin, vault_id, &StakeInstruction::SetPaused { paused })
+}
+
+pub fn set_lock_tiers(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    vault_id: u64,
+    lock_tiers: [LockTier; MAX_LOCK_TIERS],
+) -> Instruction {
+    vault_admin_instruction(program_id, admin, vault_id, &StakeInstruction::SetLockTiers { lock_tiers })
+}
+
+pub fn set_early_exit(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    vault_id: u64,
+    enabled: bool,
+    penalty_bps: u16,
+) -> Instruction {
+    vault_admin_instruction(program_id, admin, vault_id, &StakeInstruction::SetEarlyExit { enabled, penalty_bps })
+}
+
+pub fn remove_from_whitelist(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    vault_id: u64,
+    whitelist_key: &Pubkey,
+) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, vault_id);
+    let (whitelist, _) = find_whitelist_address(program_id, &vault, whitelist_key);
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakeInstruction::RemoveFromWhitelist,
+        vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new_readonly(*whitelist_key, false),
+            AccountMeta::new(whitelist, false),
+            AccountMeta::new_readonly(vault, false),
+        ],
+    )
+}
+
+pub fn disable_whitelist(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    vault_id: u64,
+    whitelist_key: &Pubkey,
+    disabled: bool,
+) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, vault_id);
+    let (whitelist, _) = find_whitelist_address(program_id, &vault, whitelist_key);
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakeInstruction::DisableWhitelist { disabled },
+        vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new_readonly(*whitelist_key, false),
+            AccountMeta::new(whitelist, false),
+            AccountMeta::new_readonly(vault, false),
+        ],
+    )
+}
+
+/// `whitelist_key` is the nft's first creator, the deployed program had no collection whitelists.
+pub fn unstake_legacy(program_id: &Pubkey, staker: &Pubkey, nft_mint: &Pubkey, whitelist_key: &Pubkey) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, LEGACY_VAULT_ID);
+    let (stake, _) = find_stake_address(program_id, nft_mint);
+    let (whitelist, _) = find_whitelist_address(program_id, &vault, whitelist_key);
+    let reward_mint = legacy_reward_mint::id();
+    Instruction::new_with_borsh(
+        *program_id,
+        &StakeInstruction::UnstakeLegacy,
+        vec![
+            AccountMeta::new(*staker, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(*nft_mint, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(sysvar::rent::id(), false),
+            AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+            AccountMeta::new(stake, false),
+            AccountMeta::new_readonly(vault, false),
+            AccountMeta::new(get_associated_token_address(staker, &reward_mint), false),
+            AccountMeta::new(get_associated_token_address(&vault, &reward_mint), false),
+            AccountMeta::new(get_associated_token_address(staker, nft_mint), false),
+            AccountMeta::new(get_associated_token_address(&vault, nft_mint), false),
+            AccountMeta::new_readonly(find_metadata_address(nft_mint), false),
+            AccountMeta::new_readonly(whitelist, false),
+            AccountMeta::new_readonly(reward_mint, false),
+        ],
+    )
+}
+
+/// `nfts` holds (nft mint, whitelist key) pairs, see `stake`.
+pub fn stake_many(
+    program_id: &Pubkey,
+    staker: &Pubkey,
+    vault_id: u64,
+    nfts: &[(Pubkey, Pubkey)],
+    lock_tier: Option<u8>,
+) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, vault_id);
+    let mut accounts = vec![
+        AccountMeta::new(*staker, true),
+        AccountMeta::new_readonly(vault, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+        AccountMeta::new(find_stats_address(program_id, &vault).0, false),
+    ];
+    for (nft_mint, whitelist_key) in nfts {
+        accounts.extend_from_slice(&[
+            AccountMeta::new_readonly(*nft_mint, false),
+            AccountMeta::new_readonly(find_metadata_address(nft_mint), false),
+            AccountMeta::new_readonly(find_master_edition_address(nft_mint), false),
+            AccountMeta::new(get_associated_token_address(staker, nft_mint), false),
+            AccountMeta::new(get_associated_token_address(&vault, nft_mint), false),
+            AccountMeta::new(find_stake_address(program_id, nft_mint).0, false),
+            AccountMeta::new_readonly(find_whitelist_address(program_id, &vault, whitelist_key).0, false),
+            AccountMeta::new_readonly(find_boost_address(program_id, &vault, nft_mint).0, false),
+        ]);
+    }
+    accounts.push(AccountMeta::new(find_staker_stats_address(program_id, &vault, staker).0, false));
+    Instruction::new_with_borsh(*program_id, &StakeInstruction::StakeMany { lock_tier }, accounts)
+}
+
+/// `nfts` holds (nft mint, whitelist key) pairs, see `stake`.
+pub fn unstake_many(
+    program_id: &Pubkey,
+    staker: &Pubkey,
+    vault_id: u64,
+    reward_mint: &Pubkey,
+    nfts: &[(Pubkey, Pubkey)],
+) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, vault_id);
+    let mut accounts = vec![
+        AccountMeta::new(*staker, true),
+        AccountMeta::new_readonly(vault, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+        AccountMeta::new_readonly(spl_associated_token_account::id(), false),
+        AccountMeta::new(find_stats_address(program_id, &vault).0, false),
+        AccountMeta::new(get_associated_token_address(staker, reward_mint), false),
+        AccountMeta::new(get_associated_token_address(&vault, reward_mint), false),
+        AccountMeta::new_readonly(*reward_mint, false),
+        AccountMeta::new_readonly(spl_token_metadata::id(), false),
+    ];
+    for (nft_mint, whitelist_key) in nfts {
+        accounts.extend_from_slice(&[
+            AccountMeta::new_readonly(*nft_mint, false),
+            AccountMeta::new_readonly(find_metadata_address(nft_mint), false),
+            AccountMeta::new_readonly(find_master_edition_address(nft_mint), false),
+            AccountMeta::new(get_associated_token_address(staker, nft_mint), false),
+            AccountMeta::new(get_associated_token_address(&vault, nft_mint), false),
+            AccountMeta::new(find_stake_address(program_id, nft_mint).0, false),
+            AccountMeta::new_readonly(find_whitelist_address(program_id, &vault, whitelist_key).0, false),
+            AccountMeta::new_readonly(find_boost_address(program_id, &vault, nft_mint).0, false),
+        ]);
+    }
+    accounts.push(AccountMeta::new(find_staker_stats_address(program_id, &vault, staker).0, false));
+    Instruction::new_with_borsh(*program_id, &StakeInstruction::UnstakeMany, accounts)
+}
+
+/// `boosts` holds (nft mint, multiplier in bps) pairs.
+pub fn set_boosts(program_id: &Pubkey, admin: &Pubkey, vault_id: u64, boosts: &[(Pubkey, u16)]) -> Instruction {
+    let (vault, _) = find_vault_address(program_id, vault_id);
+    let mut accounts = vec![
+        AccountMeta::new(*admin, true),
+        AccountMeta::new_readonly(vault, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+    for (nft_mint, _) in boosts {
+        accounts.extend_from_slice(&[
+            AccountMeta::new_readonly(*nft_mint, false),
+            AccountMeta::new(find_boost_address(program_id, &vault, nft_mint).0, false),
+        ]);
+    }
+    let multipliers_bps = boosts.iter().map(|(_, multiplier_bps)| *multiplier_bps).collect();
+    Instruction::new_with_borsh(*program_id, &StakeInstruction::SetBoosts { multipliers_bps }, accounts)
+}
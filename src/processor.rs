@@ -0,0 +1,1913 @@
+use std::convert::TryFrom;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
+};
+use spl_token_metadata::state::Key;
+
+use crate::{
+    error::StakingError,
+    instruction::StakeInstruction,
+    state::{
+        find_boost_address, find_master_edition_address, find_metadata_address, find_stake_address,
+        find_staker_stats_address, find_stats_address, find_vault_address, find_whitelist_address, vault_seeds,
+        whitelist_seeds, AccountKey, BoostData, ContractData, CustodyMode, LegacyStakeData, LockTier,
+        MetadataCollection, RateData, RewardMode, StakeData, StakerStats, VaultStats, VerificationMode, BOOST_SEED,
+        BPS_DENOMINATOR, LEGACY_VAULT_ID, MAX_BOOST_BPS, MAX_LOCK_TIERS, STAKER_SEED, STATS_SEED,
+    },
+};
+
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    // try_from_slice also rejects trailing bytes
+    let instruction =
+        StakeInstruction::try_from_slice(instruction_data).map_err(|_| StakingError::InvalidInstruction)?;
+
+    match instruction {
+        StakeInstruction::GenerateVault {
+            vault_id,
+            min_period,
+            reward_period,
+            reward_mode,
+        } => process_generate_vault(program_id, accounts, vault_id, min_period, reward_period, reward_mode),
+        StakeInstruction::Stake { lock_tier } => process_stake(program_id, accounts, lock_tier),
+        StakeInstruction::Unstake => process_unstake(program_id, accounts),
+        StakeInstruction::AddToWhitelist { price, mode } => process_add_to_whitelist(program_id, accounts, price, mode),
+        StakeInstruction::Withdraw { amount } => process_withdraw(program_id, accounts, amount),
+        // SetAdmin predates the two-step handover and is kept for older clients only
+        StakeInstruction::SetAdmin { new_admin } | StakeInstruction::ProposeAdmin { new_admin } => {
+            process_propose_admin(program_id, accounts, new_admin)
+        }
+        StakeInstruction::AcceptAdmin => process_accept_admin(program_id, accounts),
+        StakeInstruction::Claim => process_claim(program_id, accounts),
+        StakeInstruction::UpdateVaultConfig {
+            min_period,
+            reward_period,
+            reward_mode,
+        } => process_update_vault_config(program_id, accounts, min_period, reward_period, reward_mode),
+        StakeInstruction::RemoveFromWhitelist => process_remove_from_whitelist(program_id, accounts),
+        StakeInstruction::DisableWhitelist { disabled } => process_disable_whitelist(program_id, accounts, disabled),
+        StakeInstruction::SetPaused { paused } => process_set_paused(program_id, accounts, paused),
+        StakeInstruction::EmergencyUnstake => process_emergency_unstake(program_id, accounts),
+        StakeInstruction::StakeMany { lock_tier } => process_stake_many(program_id, accounts, lock_tier),
+        StakeInstruction::UnstakeMany => process_unstake_many(program_id, accounts),
+        StakeInstruction::StakeInWallet { lock_tier } => process_stake_in_wallet(program_id, accounts, lock_tier),
+        StakeInstruction::SetLockTiers { lock_tiers } => process_set_lock_tiers(program_id, accounts, lock_tiers),
+        StakeInstruction::SetEarlyExit { enabled, penalty_bps } => {
+            process_set_early_exit(program_id, accounts, enabled, penalty_bps)
+        }
+        StakeInstruction::SetBoosts { multipliers_bps } => process_set_boosts(program_id, accounts, multipliers_bps),
+        StakeInstruction::UnstakeLegacy => process_unstake_legacy(program_id, accounts),
+    }
+}
+
+fn process_generate_vault(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    vault_id: u64,
+    min_period: u64,
+    reward_period: u64,
+    reward_mode: RewardMode,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+    let rent_info = next_account_info(accounts_iter)?;
+    let reward_mint_info = next_account_info(accounts_iter)?;
+
+    let rent = &Rent::from_account_info(rent_info)?;
+    let now = unix_timestamp()?;
+
+    let (vault_address, vault_bump) = find_vault_address(program_id, vault_id);
+    if vault_address != *vault_info.key {
+        return Err(StakingError::WrongVault.into());
+    }
+
+    if !payer.is_signer {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    // token accounts are owned by the token program too
+    if *reward_mint_info.owner != spl_token::id()
+        || spl_token::state::Mint::unpack(&reward_mint_info.data.borrow()).is_err()
+    {
+        return Err(StakingError::InvalidRewardMint.into());
+    }
+
+    if reward_period == 0 {
+        return Err(StakingError::InvalidRewardPeriod.into());
+    }
+
+    if vault_info.owner == program_id {
+        // use UpdateVaultConfig to change an existing vault
+        return Err(StakingError::VaultAlreadyExists.into());
+    }
+
+    create_pda_account(
+        payer,
+        vault_info,
+        system_program,
+        rent,
+        ContractData::LEN,
+        program_id,
+        &vault_signer_seeds(&vault_id.to_le_bytes(), &[vault_bump]),
+    )?;
+
+    let vault_data = ContractData {
+        key: AccountKey::Vault,
+        version: ContractData::VERSION,
+        vault_id,
+        min_period,
+        reward_period,
+        admin: *payer.key,
+        pending_admin: Pubkey::default(),
+        reward_mint: *reward_mint_info.key,
+        reward_mode,
+        reward_index: 0,
+        reward_index_updated_at: now,
+        paused: false,
+        lock_tiers: [LockTier::default(); MAX_LOCK_TIERS],
+        early_exit: false,
+        early_exit_penalty_bps: 0,
+    };
+    save_vault(vault_info, &vault_data)
+}
+
+// Accounts shared by all nfts of a Stake, Unstake or batch instruction
+struct VaultAccounts<'a, 'b> {
+    payer: &'b AccountInfo<'a>,
+    vault_info: &'b AccountInfo<'a>,
+    token_program: &'b AccountInfo<'a>,
+    system_program: &'b AccountInfo<'a>,
+    rent_info: &'b AccountInfo<'a>,
+    assoc_program: &'b AccountInfo<'a>,
+}
+
+struct NftAccounts<'a, 'b> {
+    mint: &'b AccountInfo<'a>,
+    metadata_info: &'b AccountInfo<'a>,
+    master_edition_info: &'b AccountInfo<'a>,
+    payer_nft_holder_info: &'b AccountInfo<'a>,
+    vault_nft_holder_info: &'b AccountInfo<'a>,
+    stake_info: &'b AccountInfo<'a>,
+    whitelist_info: &'b AccountInfo<'a>,
+    // staking requires the boost PDA, Claim and Unstake may leave it out and keep the stake's copy
+    boost_info: Option<&'b AccountInfo<'a>>,
+}
+
+// accounts of each nft in a StakeMany or UnstakeMany
+const NFT_GROUP_LEN: usize = 8;
+
+fn process_stake(program_id: &Pubkey, accounts: &[AccountInfo], lock_tier: Option<u8>) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let mint = next_account_info(accounts_iter)?;
+    let metadata_info = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+    let source = next_account_info(accounts_iter)?;
+    let destination = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let rent_info = next_account_info(accounts_iter)?;
+    let assoc_program = next_account_info(accounts_iter)?;
+    let stake_info = next_account_info(accounts_iter)?;
+    let whitelist_info = next_account_info(accounts_iter)?;
+    let master_edition_info = next_account_info(accounts_iter)?;
+    let stats_info = next_account_info(accounts_iter)?;
+    let boost_info = next_account_info(accounts_iter)?;
+    let staker_stats_info = accounts_iter.next();
+
+    let shared = VaultAccounts {
+        payer,
+        vault_info,
+        token_program,
+        system_program,
+        rent_info,
+        assoc_program,
+    };
+    let nft = NftAccounts {
+        mint,
+        metadata_info,
+        master_edition_info,
+        payer_nft_holder_info: source,
+        vault_nft_holder_info: destination,
+        stake_info,
+        whitelist_info,
+        boost_info: Some(boost_info),
+    };
+    stake_nfts(program_id, &shared, stats_info, staker_stats_info, lock_tier, &[nft])
+}
+
+fn process_stake_many(program_id: &Pubkey, accounts: &[AccountInfo], lock_tier: Option<u8>) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let rent_info = next_account_info(accounts_iter)?;
+    let assoc_program = next_account_info(accounts_iter)?;
+    let stats_info = next_account_info(accounts_iter)?;
+
+    let shared = VaultAccounts {
+        payer,
+        vault_info,
+        token_program,
+        system_program,
+        rent_info,
+        assoc_program,
+    };
+    let (nft_accounts, staker_stats_info) = split_staker_stats(accounts_iter.as_slice(), NFT_GROUP_LEN);
+    let nfts = nft_groups(nft_accounts, NFT_GROUP_LEN)?;
+    stake_nfts(program_id, &shared, stats_info, staker_stats_info, lock_tier, &nfts)
+}
+
+// The vault, lock tier and stats are loaded once for all nfts
+fn stake_nfts<'a, 'b>(
+    program_id: &Pubkey,
+    shared: &VaultAccounts<'a, 'b>,
+    stats_info: &'b AccountInfo<'a>,
+    staker_stats_info: Option<&'b AccountInfo<'a>>,
+    lock_tier: Option<u8>,
+    nfts: &[NftAccounts<'a, 'b>],
+) -> ProgramResult {
+    let rent = &Rent::from_account_info(shared.rent_info)?;
+    let now = unix_timestamp()?;
+
+    let (vault_data, _vault_bump) =
+        load_stake_vault(program_id, shared.payer, shared.vault_info, shared.token_program)?;
+    let lock_tier = vault_data.lock_tier(lock_tier)?;
+
+    let (mut stats, mut staker_stats) = load_stats(
+        program_id,
+        shared.payer,
+        shared.vault_info,
+        stats_info,
+        staker_stats_info,
+        shared.system_program,
+        rent,
+    )?;
+
+    for nft in nfts {
+        open_stake(
+            program_id,
+            shared.payer,
+            shared.vault_info,
+            shared.system_program,
+            &vault_data,
+            rent,
+            now,
+            &lock_tier,
+            nft,
+            CustodyMode::Vault,
+        )?;
+        deposit_nft(shared, nft)?;
+        stats.record_stake(staker_stats.as_mut())?;
+    }
+
+    save_stats(stats_info, &stats, staker_stats_info, staker_stats.as_ref())
+}
+
+fn process_stake_in_wallet(program_id: &Pubkey, accounts: &[AccountInfo], lock_tier: Option<u8>) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let mint = next_account_info(accounts_iter)?;
+    let metadata_info = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+    let source = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let rent_info = next_account_info(accounts_iter)?;
+    let stake_info = next_account_info(accounts_iter)?;
+    let whitelist_info = next_account_info(accounts_iter)?;
+    let master_edition_info = next_account_info(accounts_iter)?;
+    let metadata_program = next_account_info(accounts_iter)?;
+    let stats_info = next_account_info(accounts_iter)?;
+    let boost_info = next_account_info(accounts_iter)?;
+    let staker_stats_info = accounts_iter.next();
+
+    let rent = &Rent::from_account_info(rent_info)?;
+    let now = unix_timestamp()?;
+
+    // the nft stays in place, the staker's ATA is also the one holding it while staked
+    let nft = NftAccounts {
+        mint,
+        metadata_info,
+        master_edition_info,
+        payer_nft_holder_info: source,
+        vault_nft_holder_info: source,
+        stake_info,
+        whitelist_info,
+        boost_info: Some(boost_info),
+    };
+
+    let (vault_data, vault_bump) = load_stake_vault(program_id, payer, vault_info, token_program)?;
+    let lock_tier = vault_data.lock_tier(lock_tier)?;
+
+    if *metadata_program.key != spl_token_metadata::id() {
+        return Err(StakingError::WrongMetadataProgram.into());
+    }
+
+    let (mut stats, mut staker_stats) =
+        load_stats(program_id, payer, vault_info, stats_info, staker_stats_info, system_program, rent)?;
+
+    open_stake(
+        program_id,
+        payer,
+        vault_info,
+        system_program,
+        &vault_data,
+        rent,
+        now,
+        &lock_tier,
+        &nft,
+        CustodyMode::Wallet,
+    )?;
+
+    invoke(
+        &spl_token::instruction::approve(token_program.key, source.key, vault_info.key, payer.key, &[], 1)?,
+        &[source.clone(), vault_info.clone(), payer.clone(), token_program.clone()],
+    )?;
+    invoke_signed(
+        &delegated_account_instruction(
+            FREEZE_DELEGATED_ACCOUNT,
+            vault_info.key,
+            source.key,
+            master_edition_info.key,
+            mint.key,
+        ),
+        &[
+            vault_info.clone(),
+            source.clone(),
+            master_edition_info.clone(),
+            mint.clone(),
+            token_program.clone(),
+            metadata_program.clone(),
+        ],
+        &[&vault_signer_seeds(&vault_data.vault_id.to_le_bytes(), &[vault_bump])],
+    )?;
+
+    stats.record_stake(staker_stats.as_mut())?;
+    save_stats(stats_info, &stats, staker_stats_info, staker_stats.as_ref())
+}
+
+// Token program, signer and vault checks shared by every way of staking
+fn load_stake_vault(
+    program_id: &Pubkey,
+    payer: &AccountInfo,
+    vault_info: &AccountInfo,
+    token_program: &AccountInfo,
+) -> Result<(ContractData, u8), ProgramError> {
+    if *token_program.key != spl_token::id() {
+        return Err(StakingError::WrongTokenProgram.into());
+    }
+
+    if !payer.is_signer {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    let (vault_data, vault_bump) = load_vault(program_id, vault_info)?;
+    if vault_data.paused {
+        return Err(StakingError::VaultPaused.into());
+    }
+    Ok((vault_data, vault_bump))
+}
+
+// Checks the nft against the whitelist and writes its stake, the caller takes custody of the nft
+#[allow(clippy::too_many_arguments)]
+fn open_stake<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    vault_info: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    vault_data: &ContractData,
+    rent: &Rent,
+    now: u64,
+    lock_tier: &LockTier,
+    nft: &NftAccounts<'a, '_>,
+    custody: CustodyMode,
+) -> ProgramResult {
+    let (stake_address, stake_bump) = find_stake_address(program_id, nft.mint.key);
+    if stake_address != *nft.stake_info.key {
+        return Err(StakingError::WrongStakeData.into());
+    }
+
+    assert_nft_mint(nft.mint, nft.master_edition_info)?;
+
+    let rate_data =
+        load_collection_rate(program_id, vault_info, nft.mint, nft.metadata_info, nft.whitelist_info)?
+        .ok_or(StakingError::NotWhitelisted)?;
+    if rate_data.disabled {
+        return Err(StakingError::WhitelistDisabled.into());
+    }
+
+    let boost_bps = load_boost(program_id, vault_info, nft.mint, nft.boost_info)?.unwrap_or(BPS_DENOMINATOR as u16);
+
+    if spl_associated_token_account::get_associated_token_address(payer.key, nft.mint.key)
+        != *nft.payer_nft_holder_info.key
+    {
+        return Err(StakingError::WrongPayerNftHolder.into());
+    }
+
+    if nft.stake_info.owner != program_id {
+        create_pda_account(
+            payer,
+            nft.stake_info,
+            system_program,
+            rent,
+            StakeData::LEN,
+            program_id,
+            &[&nft.mint.key.to_bytes(), &[stake_bump]],
+        )?;
+    } else {
+        // restaking reuses the account, which has to be on the current layout
+        StakeData::unpack(&nft.stake_info.data.borrow())?;
+    }
+
+    let stake_data = StakeData {
+        key: AccountKey::Stake,
+        version: StakeData::VERSION,
+        timestamp: now,
+        staker: *payer.key,
+        active: true,
+        vault: *vault_info.key,
+        claimed_at: now,
+        reward_index: vault_data.current_reward_index(now)?,
+        reward_carry: 0,
+        custody,
+        lock_end: now.checked_add(lock_tier.duration).ok_or(StakingError::MathOverflow)?,
+        multiplier_bps: lock_tier.multiplier_bps,
+        boost_bps,
+    };
+    stake_data.serialize(&mut &mut nft.stake_info.data.borrow_mut()[..])?;
+    Ok(())
+}
+
+// Moves the nft into the vault's ATA
+fn deposit_nft<'a>(shared: &VaultAccounts<'a, '_>, nft: &NftAccounts<'a, '_>) -> ProgramResult {
+    let payer = shared.payer;
+    let vault_info = shared.vault_info;
+
+    if spl_associated_token_account::get_associated_token_address(vault_info.key, nft.mint.key)
+        != *nft.vault_nft_holder_info.key
+    {
+        return Err(StakingError::WrongVaultNftHolder.into());
+    }
+
+    create_ata_if_missing(
+        payer,
+        vault_info,
+        nft.vault_nft_holder_info,
+        nft.mint,
+        shared.system_program,
+        shared.token_program,
+        shared.rent_info,
+        shared.assoc_program,
+    )?;
+    invoke(
+        &spl_token::instruction::transfer(
+            shared.token_program.key,
+            nft.payer_nft_holder_info.key,
+            nft.vault_nft_holder_info.key,
+            payer.key,
+            &[],
+            1,
+        )?,
+        &[
+            nft.payer_nft_holder_info.clone(),
+            nft.vault_nft_holder_info.clone(),
+            payer.clone(),
+            shared.token_program.clone(),
+        ],
+    )?;
+    Ok(())
+}
+
+fn process_unstake(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let nft_info = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let rent_info = next_account_info(accounts_iter)?;
+    let assoc_program = next_account_info(accounts_iter)?;
+    let stake_info = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+    let payer_reward_holder_info = next_account_info(accounts_iter)?;
+    let vault_reward_holder_info = next_account_info(accounts_iter)?;
+    let payer_nft_holder_info = next_account_info(accounts_iter)?;
+    let vault_nft_holder_info = next_account_info(accounts_iter)?;
+    let metadata_info = next_account_info(accounts_iter)?;
+    let whitelist_info = next_account_info(accounts_iter)?;
+    let reward_mint_info = next_account_info(accounts_iter)?;
+    let stats_info = next_account_info(accounts_iter)?;
+    let master_edition_info = next_account_info(accounts_iter)?;
+    let metadata_program = next_account_info(accounts_iter)?;
+    let boost_info = accounts_iter.next();
+    let staker_stats_info = accounts_iter.next();
+
+    let shared = VaultAccounts {
+        payer,
+        vault_info,
+        token_program,
+        system_program,
+        rent_info,
+        assoc_program,
+    };
+    let nft = NftAccounts {
+        mint: nft_info,
+        metadata_info,
+        master_edition_info,
+        payer_nft_holder_info,
+        vault_nft_holder_info,
+        stake_info,
+        whitelist_info,
+        boost_info,
+    };
+    unstake_nfts(
+        program_id,
+        &shared,
+        payer_reward_holder_info,
+        vault_reward_holder_info,
+        reward_mint_info,
+        metadata_program,
+        stats_info,
+        staker_stats_info,
+        &[nft],
+    )
+}
+
+fn process_unstake_many(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let rent_info = next_account_info(accounts_iter)?;
+    let assoc_program = next_account_info(accounts_iter)?;
+    let stats_info = next_account_info(accounts_iter)?;
+    let payer_reward_holder_info = next_account_info(accounts_iter)?;
+    let vault_reward_holder_info = next_account_info(accounts_iter)?;
+    let reward_mint_info = next_account_info(accounts_iter)?;
+    let metadata_program = next_account_info(accounts_iter)?;
+
+    let shared = VaultAccounts {
+        payer,
+        vault_info,
+        token_program,
+        system_program,
+        rent_info,
+        assoc_program,
+    };
+    let (nft_accounts, staker_stats_info) = split_staker_stats(accounts_iter.as_slice(), NFT_GROUP_LEN);
+    let nfts = nft_groups(nft_accounts, NFT_GROUP_LEN)?;
+    unstake_nfts(
+        program_id,
+        &shared,
+        payer_reward_holder_info,
+        vault_reward_holder_info,
+        reward_mint_info,
+        metadata_program,
+        stats_info,
+        staker_stats_info,
+        &nfts,
+    )
+}
+
+// The reward accounts are checked once and the rewards of all nfts are paid in one transfer
+#[allow(clippy::too_many_arguments)]
+fn unstake_nfts<'a, 'b>(
+    program_id: &Pubkey,
+    shared: &VaultAccounts<'a, 'b>,
+    payer_reward_holder_info: &'b AccountInfo<'a>,
+    vault_reward_holder_info: &'b AccountInfo<'a>,
+    reward_mint_info: &'b AccountInfo<'a>,
+    metadata_program: &'b AccountInfo<'a>,
+    stats_info: &'b AccountInfo<'a>,
+    staker_stats_info: Option<&'b AccountInfo<'a>>,
+    nfts: &[NftAccounts<'a, 'b>],
+) -> ProgramResult {
+    let payer = shared.payer;
+    let rent = &Rent::from_account_info(shared.rent_info)?;
+
+    let now = unix_timestamp()?;
+
+    if *shared.token_program.key != spl_token::id() {
+        return Err(StakingError::WrongTokenProgram.into());
+    }
+
+    let (vault_data, vault_bump) = load_vault(program_id, shared.vault_info)?;
+    assert_reward_accounts(
+        &vault_data,
+        payer,
+        shared.vault_info,
+        payer_reward_holder_info,
+        vault_reward_holder_info,
+        reward_mint_info,
+    )?;
+
+    let (mut stats, mut staker_stats) = load_stats(
+        program_id,
+        payer,
+        shared.vault_info,
+        stats_info,
+        staker_stats_info,
+        shared.system_program,
+        rent,
+    )?;
+
+    let vault_id = vault_data.vault_id.to_le_bytes();
+    let vault_bump = [vault_bump];
+    let vault_seeds = &vault_signer_seeds(&vault_id, &vault_bump);
+
+    let mut total_reward: u64 = 0;
+    for nft in nfts {
+        let reward = unstake_nft(program_id, shared, &vault_data, now, vault_seeds, metadata_program, nft)?;
+        total_reward = total_reward.checked_add(reward).ok_or(StakingError::MathOverflow)?;
+        stats.record_reward(staker_stats.as_mut(), reward)?;
+        stats.record_unstake(staker_stats.as_mut());
+    }
+
+    if total_reward > 0 {
+        create_ata_if_missing(
+            payer,
+            payer,
+            payer_reward_holder_info,
+            reward_mint_info,
+            shared.system_program,
+            shared.token_program,
+            shared.rent_info,
+            shared.assoc_program,
+        )?;
+        transfer_from_vault(
+            shared.token_program,
+            vault_reward_holder_info,
+            payer_reward_holder_info,
+            shared.vault_info,
+            total_reward,
+            vault_seeds,
+        )?;
+    }
+
+    save_stats(stats_info, &stats, staker_stats_info, staker_stats.as_ref())
+}
+
+// Returns the nft and the reward it earned, the caller pays the reward
+fn unstake_nft<'a>(
+    program_id: &Pubkey,
+    shared: &VaultAccounts<'a, '_>,
+    vault_data: &ContractData,
+    now: u64,
+    vault_seeds: &[&[u8]],
+    metadata_program: &AccountInfo<'a>,
+    nft: &NftAccounts<'a, '_>,
+) -> Result<u64, ProgramError> {
+    let payer = shared.payer;
+    let vault_info = shared.vault_info;
+
+    assert_nft_holders(payer, vault_info, nft.mint, nft.payer_nft_holder_info, nft.vault_nft_holder_info)?;
+
+    // a collection removed from the whitelist no longer earns rewards
+    let price =
+        load_collection_rate(program_id, vault_info, nft.mint, nft.metadata_info, nft.whitelist_info)?
+        .map_or(0, |rate_data| rate_data.price);
+
+    let mut stake_data = load_active_stake(program_id, nft.stake_info, nft.mint, payer, vault_info)?;
+
+    // a clock behind the stake timestamp counts as no time staked
+    let early = !vault_data.paused && now.saturating_sub(stake_data.timestamp) < vault_data.min_period;
+    if early && !vault_data.early_exit {
+        return Err(StakingError::MinPeriodNotReached.into());
+    }
+
+    // a lock is a commitment, early exit does not cover it
+    if !vault_data.paused && now < stake_data.lock_end {
+        return Err(StakingError::LockNotExpired.into());
+    }
+
+    let reward = if vault_data.paused {
+        // emergency path, the nft is returned without rewards
+        0
+    } else {
+        stake_data.settle_reward(vault_data, price, now)?
+    };
+    // settled at the stored boost, the stake keeps the current one for the record
+    if let Some(boost_bps) = load_boost(program_id, vault_info, nft.mint, nft.boost_info)? {
+        stake_data.boost_bps = boost_bps;
+    }
+
+    let reward = if early {
+        let penalty = vault_data.early_exit_penalty(reward)?;
+        msg!("early exit withholds {:?}", penalty);
+        reward - penalty
+    } else {
+        reward
+    };
+
+    release_nft(
+        shared,
+        &stake_data,
+        nft.mint,
+        nft.master_edition_info,
+        nft.payer_nft_holder_info,
+        nft.vault_nft_holder_info,
+        metadata_program,
+        vault_seeds,
+    )?;
+
+    stake_data.active = false;
+    stake_data.serialize(&mut &mut nft.stake_info.data.borrow_mut()[..])?;
+    Ok(reward)
+}
+
+// Hands the nft back the way it was staked
+#[allow(clippy::too_many_arguments)]
+fn release_nft<'a>(
+    shared: &VaultAccounts<'a, '_>,
+    stake_data: &StakeData,
+    nft_info: &AccountInfo<'a>,
+    master_edition_info: &AccountInfo<'a>,
+    payer_nft_holder_info: &AccountInfo<'a>,
+    vault_nft_holder_info: &AccountInfo<'a>,
+    metadata_program: &AccountInfo<'a>,
+    vault_seeds: &[&[u8]],
+) -> ProgramResult {
+    match stake_data.custody {
+        CustodyMode::Vault => return_nft(
+            shared.payer,
+            nft_info,
+            payer_nft_holder_info,
+            vault_nft_holder_info,
+            shared.vault_info,
+            shared.system_program,
+            shared.token_program,
+            shared.rent_info,
+            shared.assoc_program,
+            vault_seeds,
+        ),
+        CustodyMode::Wallet => {
+            if *metadata_program.key != spl_token_metadata::id() {
+                return Err(StakingError::WrongMetadataProgram.into());
+            }
+            thaw_nft(
+                shared.payer,
+                nft_info,
+                payer_nft_holder_info,
+                master_edition_info,
+                shared.vault_info,
+                shared.token_program,
+                metadata_program,
+                vault_seeds,
+            )
+        }
+    }
+}
+
+// The deployed program's stakes are too small for the current layout, they are unstaked in place
+// and closed so the nft can be staked again
+fn process_unstake_legacy(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let nft_info = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let rent_info = next_account_info(accounts_iter)?;
+    let assoc_program = next_account_info(accounts_iter)?;
+    let stake_info = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+    let payer_reward_holder_info = next_account_info(accounts_iter)?;
+    let vault_reward_holder_info = next_account_info(accounts_iter)?;
+    let payer_nft_holder_info = next_account_info(accounts_iter)?;
+    let vault_nft_holder_info = next_account_info(accounts_iter)?;
+    let metadata_info = next_account_info(accounts_iter)?;
+    let whitelist_info = next_account_info(accounts_iter)?;
+    let reward_mint_info = next_account_info(accounts_iter)?;
+
+    let now = unix_timestamp()?;
+
+    if *token_program.key != spl_token::id() {
+        return Err(StakingError::WrongTokenProgram.into());
+    }
+
+    let (vault_data, vault_bump) = load_legacy_vault(program_id, vault_info)?;
+    assert_reward_accounts(
+        &vault_data,
+        payer,
+        vault_info,
+        payer_reward_holder_info,
+        vault_reward_holder_info,
+        reward_mint_info,
+    )?;
+    assert_nft_holders(payer, vault_info, nft_info, payer_nft_holder_info, vault_nft_holder_info)?;
+
+    let (stake_address, _stake_bump) = find_stake_address(program_id, nft_info.key);
+    if stake_address != *stake_info.key {
+        return Err(StakingError::WrongStakeData.into());
+    }
+    if stake_info.data_len() != LegacyStakeData::LEN {
+        return Err(StakingError::NotLegacyAccount.into());
+    }
+    let stake_data =
+        LegacyStakeData::try_from_slice(&stake_info.data.borrow()).map_err(|_| StakingError::InvalidStakeData)?;
+    if !stake_data.active {
+        return Err(StakingError::StakeInactive.into());
+    }
+    if stake_data.staker != *payer.key {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    let price = load_collection_rate(program_id, vault_info, nft_info, metadata_info, whitelist_info)?
+        .map_or(0, |rate_data| rate_data.price);
+
+    let elapsed = now.saturating_sub(stake_data.timestamp);
+    if !vault_data.paused && elapsed < vault_data.min_period {
+        return Err(StakingError::MinPeriodNotReached.into());
+    }
+
+    let reward = if vault_data.paused {
+        0
+    } else {
+        elapsed
+            .checked_div(vault_data.reward_period)
+            .and_then(|periods| periods.checked_mul(price))
+            .ok_or(StakingError::MathOverflow)?
+    };
+
+    let vault_id = vault_data.vault_id.to_le_bytes();
+    let vault_bump = [vault_bump];
+    let vault_seeds = &vault_signer_seeds(&vault_id, &vault_bump);
+    if reward > 0 {
+        create_ata_if_missing(
+            payer,
+            payer,
+            payer_reward_holder_info,
+            reward_mint_info,
+            system_program,
+            token_program,
+            rent_info,
+            assoc_program,
+        )?;
+        transfer_from_vault(
+            token_program,
+            vault_reward_holder_info,
+            payer_reward_holder_info,
+            vault_info,
+            reward,
+            vault_seeds,
+        )?;
+    }
+
+    return_nft(
+        payer,
+        nft_info,
+        payer_nft_holder_info,
+        vault_nft_holder_info,
+        vault_info,
+        system_program,
+        token_program,
+        rent_info,
+        assoc_program,
+        vault_seeds,
+    )?;
+
+    // rent goes back to the staker
+    close_account(stake_info, payer)
+}
+
+fn process_claim(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let nft_info = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let rent_info = next_account_info(accounts_iter)?;
+    let assoc_program = next_account_info(accounts_iter)?;
+    let stake_info = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+    let payer_reward_holder_info = next_account_info(accounts_iter)?;
+    let vault_reward_holder_info = next_account_info(accounts_iter)?;
+    let metadata_info = next_account_info(accounts_iter)?;
+    let whitelist_info = next_account_info(accounts_iter)?;
+    let reward_mint_info = next_account_info(accounts_iter)?;
+    let stats_info = next_account_info(accounts_iter)?;
+    let boost_info = accounts_iter.next();
+    let staker_stats_info = accounts_iter.next();
+
+    let rent = &Rent::from_account_info(rent_info)?;
+
+    let now = unix_timestamp()?;
+
+    if *token_program.key != spl_token::id() {
+        return Err(StakingError::WrongTokenProgram.into());
+    }
+
+    let (vault_data, vault_bump) = load_vault(program_id, vault_info)?;
+    if vault_data.paused {
+        return Err(StakingError::VaultPaused.into());
+    }
+    assert_reward_accounts(
+        &vault_data,
+        payer,
+        vault_info,
+        payer_reward_holder_info,
+        vault_reward_holder_info,
+        reward_mint_info,
+    )?;
+
+    let rate_data =
+        load_collection_rate(program_id, vault_info, nft_info, metadata_info, whitelist_info)?
+        .ok_or(StakingError::NotWhitelisted)?;
+
+    let mut stake_data = load_active_stake(program_id, stake_info, nft_info, payer, vault_info)?;
+    // claiming early would dodge the early exit penalty on the later unstake
+    if vault_data.early_exit && now.saturating_sub(stake_data.timestamp) < vault_data.min_period {
+        return Err(StakingError::MinPeriodNotReached.into());
+    }
+    let reward = stake_data.settle_reward(&vault_data, rate_data.price, now)?;
+    // like the reward index, the stored boost pays up to now and the current one from here on
+    if let Some(boost_bps) = load_boost(program_id, vault_info, nft_info, boost_info)? {
+        stake_data.boost_bps = boost_bps;
+    }
+
+    if reward > 0 {
+        create_ata_if_missing(
+            payer,
+            payer,
+            payer_reward_holder_info,
+            reward_mint_info,
+            system_program,
+            token_program,
+            rent_info,
+            assoc_program,
+        )?;
+        transfer_from_vault(
+            token_program,
+            vault_reward_holder_info,
+            payer_reward_holder_info,
+            vault_info,
+            reward,
+            &vault_signer_seeds(&vault_data.vault_id.to_le_bytes(), &[vault_bump]),
+        )?;
+    }
+
+    stake_data.serialize(&mut &mut stake_info.data.borrow_mut()[..])?;
+
+    let (mut stats, mut staker_stats) =
+        load_stats(program_id, payer, vault_info, stats_info, staker_stats_info, system_program, rent)?;
+    stats.record_reward(staker_stats.as_mut(), reward)?;
+    save_stats(stats_info, &stats, staker_stats_info, staker_stats.as_ref())?;
+    Ok(())
+}
+
+fn process_emergency_unstake(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let nft_info = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let rent_info = next_account_info(accounts_iter)?;
+    let assoc_program = next_account_info(accounts_iter)?;
+    let stake_info = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+    let payer_nft_holder_info = next_account_info(accounts_iter)?;
+    let vault_nft_holder_info = next_account_info(accounts_iter)?;
+    let stats_info = next_account_info(accounts_iter)?;
+    let master_edition_info = next_account_info(accounts_iter)?;
+    let metadata_program = next_account_info(accounts_iter)?;
+    let staker_stats_info = accounts_iter.next();
+
+    let rent = &Rent::from_account_info(rent_info)?;
+
+    if *token_program.key != spl_token::id() {
+        return Err(StakingError::WrongTokenProgram.into());
+    }
+
+    if !payer.is_signer {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    let (vault_data, vault_bump) = load_vault(program_id, vault_info)?;
+    assert_nft_holders(payer, vault_info, nft_info, payer_nft_holder_info, vault_nft_holder_info)?;
+    let mut stake_data = load_active_stake(program_id, stake_info, nft_info, payer, vault_info)?;
+
+    // forfeiting the rewards breaks neither min_period nor a lock, only pausing the vault does
+    let now = unix_timestamp()?;
+    if !vault_data.paused && now.saturating_sub(stake_data.timestamp) < vault_data.min_period {
+        return Err(StakingError::MinPeriodNotReached.into());
+    }
+    if !vault_data.paused && now < stake_data.lock_end {
+        return Err(StakingError::LockNotExpired.into());
+    }
+
+    let shared = VaultAccounts {
+        payer,
+        vault_info,
+        token_program,
+        system_program,
+        rent_info,
+        assoc_program,
+    };
+    // rewards are skipped on purpose, the reward accounts are not touched
+    release_nft(
+        &shared,
+        &stake_data,
+        nft_info,
+        master_edition_info,
+        payer_nft_holder_info,
+        vault_nft_holder_info,
+        metadata_program,
+        &vault_signer_seeds(&vault_data.vault_id.to_le_bytes(), &[vault_bump]),
+    )?;
+
+    let (mut stats, mut staker_stats) =
+        load_stats(program_id, payer, vault_info, stats_info, staker_stats_info, system_program, rent)?;
+    stats.record_unstake(staker_stats.as_mut());
+    save_stats(stats_info, &stats, staker_stats_info, staker_stats.as_ref())?;
+
+    stake_data.active = false;
+    stake_data.serialize(&mut &mut stake_info.data.borrow_mut()[..])?;
+    Ok(())
+}
+
+fn process_add_to_whitelist(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    price: u64,
+    mode: VerificationMode,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let whitelist_key_info = next_account_info(accounts_iter)?;
+    let whitelist_info = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let rent_info = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+
+    let rent = &Rent::from_account_info(rent_info)?;
+
+    let (vault_data, _vault_bump) = load_vault(program_id, vault_info)?;
+    assert_admin(&vault_data, payer)?;
+
+    let whitelist_seeds = whitelist_seeds(program_id, vault_info.key, whitelist_key_info.key);
+    let (whitelist_address, whitelist_bump) = Pubkey::find_program_address(&whitelist_seeds, program_id);
+    if whitelist_address != *whitelist_info.key {
+        return Err(StakingError::WrongWhitelist.into());
+    }
+
+    let rate_data = if whitelist_info.owner != program_id {
+        create_pda_account(
+            payer,
+            whitelist_info,
+            system_program,
+            rent,
+            RateData::LEN,
+            program_id,
+            &[&whitelist_seeds[..], &[&[whitelist_bump]]].concat(),
+        )?;
+        RateData {
+            key: AccountKey::Whitelist,
+            version: RateData::VERSION,
+            price,
+            disabled: false,
+            mode,
+        }
+    } else {
+        // repricing keeps the disabled flag
+        let mut rate_data = RateData::unpack_legacy(&whitelist_info.data.borrow())?;
+        rate_data.price = price;
+        rate_data.mode = mode;
+        rate_data
+    };
+    save_whitelist(whitelist_info, &rate_data)
+}
+
+fn process_remove_from_whitelist(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let whitelist_key_info = next_account_info(accounts_iter)?;
+    let whitelist_info = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+
+    let (vault_data, _vault_bump) = load_vault(program_id, vault_info)?;
+    assert_admin(&vault_data, payer)?;
+    load_whitelist(program_id, vault_info, whitelist_key_info, whitelist_info)?;
+
+    // rent goes back to the admin
+    close_account(whitelist_info, payer)
+}
+
+fn close_account(account_info: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+    let lamports = account_info.lamports();
+    **account_info.lamports.borrow_mut() = 0;
+    **destination.lamports.borrow_mut() = destination
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(StakingError::MathOverflow)?;
+    account_info.data.borrow_mut().fill(0);
+    Ok(())
+}
+
+fn process_disable_whitelist(program_id: &Pubkey, accounts: &[AccountInfo], disabled: bool) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let whitelist_key_info = next_account_info(accounts_iter)?;
+    let whitelist_info = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+
+    let (vault_data, _vault_bump) = load_vault(program_id, vault_info)?;
+    assert_admin(&vault_data, payer)?;
+    let mut rate_data = load_whitelist(program_id, vault_info, whitelist_key_info, whitelist_info)?;
+
+    rate_data.disabled = disabled;
+    save_whitelist(whitelist_info, &rate_data)
+}
+
+fn process_withdraw(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let payer_reward_holder_info = next_account_info(accounts_iter)?;
+    let vault_reward_holder_info = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+    let reward_mint_info = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let rent_info = next_account_info(accounts_iter)?;
+    let assoc_program = next_account_info(accounts_iter)?;
+
+    let (vault_data, vault_bump) = load_vault(program_id, vault_info)?;
+    assert_admin(&vault_data, payer)?;
+    assert_reward_accounts(
+        &vault_data,
+        payer,
+        vault_info,
+        payer_reward_holder_info,
+        vault_reward_holder_info,
+        reward_mint_info,
+    )?;
+
+    create_ata_if_missing(
+        payer,
+        payer,
+        payer_reward_holder_info,
+        reward_mint_info,
+        system_program,
+        token_program,
+        rent_info,
+        assoc_program,
+    )?;
+    transfer_from_vault(
+        token_program,
+        vault_reward_holder_info,
+        payer_reward_holder_info,
+        vault_info,
+        amount,
+        &vault_signer_seeds(&vault_data.vault_id.to_le_bytes(), &[vault_bump]),
+    )
+}
+
+fn process_propose_admin(program_id: &Pubkey, accounts: &[AccountInfo], new_admin: Pubkey) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+
+    let (mut vault_data, _vault_bump) = load_vault(program_id, vault_info)?;
+    assert_admin(&vault_data, payer)?;
+
+    // proposing the default key cancels a pending handover
+    vault_data.pending_admin = new_admin;
+    save_vault(vault_info, &vault_data)
+}
+
+fn process_accept_admin(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+
+    let (mut vault_data, _vault_bump) = load_vault(program_id, vault_info)?;
+
+    if vault_data.pending_admin == Pubkey::default() {
+        return Err(StakingError::NoPendingAdmin.into());
+    }
+
+    if *payer.key != vault_data.pending_admin || !payer.is_signer {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    vault_data.admin = vault_data.pending_admin;
+    vault_data.pending_admin = Pubkey::default();
+    save_vault(vault_info, &vault_data)
+}
+
+fn process_update_vault_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    min_period: u64,
+    reward_period: u64,
+    reward_mode: RewardMode,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+
+    let now = unix_timestamp()?;
+
+    let (mut vault_data, _vault_bump) = load_vault(program_id, vault_info)?;
+    assert_admin(&vault_data, payer)?;
+
+    if reward_period == 0 {
+        return Err(StakingError::InvalidRewardPeriod.into());
+    }
+
+    // checkpoint the reward index so running stakes are settled at the old rate up to now
+    vault_data.reward_index = vault_data.current_reward_index(now)?;
+    vault_data.reward_index_updated_at = now;
+
+    vault_data.min_period = min_period;
+    vault_data.reward_period = reward_period;
+    vault_data.reward_mode = reward_mode;
+    save_vault(vault_info, &vault_data)
+}
+
+fn process_set_lock_tiers(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    lock_tiers: [LockTier; MAX_LOCK_TIERS],
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+
+    let (mut vault_data, _vault_bump) = load_vault(program_id, vault_info)?;
+    assert_admin(&vault_data, payer)?;
+
+    // running stakes keep the multiplier and lock end they were staked with
+    vault_data.lock_tiers = lock_tiers;
+    save_vault(vault_info, &vault_data)
+}
+
+fn process_set_early_exit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    enabled: bool,
+    penalty_bps: u16,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+
+    let (mut vault_data, _vault_bump) = load_vault(program_id, vault_info)?;
+    assert_admin(&vault_data, payer)?;
+
+    if penalty_bps as u64 > BPS_DENOMINATOR {
+        return Err(StakingError::InvalidPenalty.into());
+    }
+
+    vault_data.early_exit = enabled;
+    vault_data.early_exit_penalty_bps = penalty_bps;
+    save_vault(vault_info, &vault_data)
+}
+
+fn process_set_boosts(program_id: &Pubkey, accounts: &[AccountInfo], multipliers_bps: Vec<u16>) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let rent_info = next_account_info(accounts_iter)?;
+
+    let rent = &Rent::from_account_info(rent_info)?;
+
+    let (vault_data, _vault_bump) = load_vault(program_id, vault_info)?;
+    assert_admin(&vault_data, payer)?;
+
+    let entries = accounts_iter.as_slice().chunks_exact(2);
+    if multipliers_bps.is_empty() || !entries.remainder().is_empty() || entries.len() != multipliers_bps.len() {
+        return Err(StakingError::InvalidNftAccounts.into());
+    }
+
+    for (entry, multiplier_bps) in entries.zip(multipliers_bps) {
+        let (mint, boost_info) = (&entry[0], &entry[1]);
+
+        if multiplier_bps == 0 || multiplier_bps > MAX_BOOST_BPS {
+            return Err(StakingError::InvalidBoost.into());
+        }
+
+        let (boost_address, boost_bump) = find_boost_address(program_id, vault_info.key, mint.key);
+        if boost_address != *boost_info.key {
+            return Err(StakingError::WrongBoost.into());
+        }
+
+        if boost_info.owner != program_id {
+            create_pda_account(
+                payer,
+                boost_info,
+                system_program,
+                rent,
+                BoostData::LEN,
+                program_id,
+                &[
+                    BOOST_SEED.as_bytes(),
+                    &vault_info.key.to_bytes(),
+                    &mint.key.to_bytes(),
+                    &[boost_bump],
+                ],
+            )?;
+        }
+
+        let boost_data = BoostData {
+            key: AccountKey::Boost,
+            version: BoostData::VERSION,
+            vault: *vault_info.key,
+            mint: *mint.key,
+            multiplier_bps,
+        };
+        boost_data.serialize(&mut &mut boost_info.data.borrow_mut()[..])?;
+    }
+    Ok(())
+}
+
+fn process_set_paused(program_id: &Pubkey, accounts: &[AccountInfo], paused: bool) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let vault_info = next_account_info(accounts_iter)?;
+
+    let (mut vault_data, _vault_bump) = load_vault(program_id, vault_info)?;
+    assert_admin(&vault_data, payer)?;
+
+    vault_data.paused = paused;
+    save_vault(vault_info, &vault_data)
+}
+
+// Cluster time as u64, a negative timestamp is rejected instead of wrapping
+fn unix_timestamp() -> Result<u64, ProgramError> {
+    let clock = Clock::get()?;
+    u64::try_from(clock.unix_timestamp).map_err(|_| StakingError::MathOverflow.into())
+}
+
+// Loads the vault and checks it sits at the PDA derived from its own vault_id
+fn load_vault(program_id: &Pubkey, vault_info: &AccountInfo) -> Result<(ContractData, u8), ProgramError> {
+    if vault_info.data_len() == ContractData::LEGACY_LEN {
+        return load_legacy_vault(program_id, vault_info);
+    }
+
+    let vault_data = ContractData::unpack(&vault_info.data.borrow())?;
+
+    let (vault_address, vault_bump) = find_vault_address(program_id, vault_data.vault_id);
+    if vault_address != *vault_info.key {
+        return Err(StakingError::WrongVault.into());
+    }
+    Ok((vault_data, vault_bump))
+}
+
+// Loads the deployed program's vault from its 16 byte layout
+fn load_legacy_vault(program_id: &Pubkey, vault_info: &AccountInfo) -> Result<(ContractData, u8), ProgramError> {
+    let (vault_address, vault_bump) = find_vault_address(program_id, LEGACY_VAULT_ID);
+    if vault_address != *vault_info.key {
+        return Err(StakingError::WrongVault.into());
+    }
+
+    let vault_data = ContractData::unpack_legacy(&vault_info.data.borrow())?;
+    Ok((vault_data, vault_bump))
+}
+
+// The deployed program's vault keeps its 16 byte layout
+fn save_vault(vault_info: &AccountInfo, vault_data: &ContractData) -> ProgramResult {
+    if vault_info.data_len() == ContractData::LEGACY_LEN {
+        let legacy = vault_data.to_legacy().ok_or(StakingError::LegacyLayoutTooSmall)?;
+        legacy.serialize(&mut &mut vault_info.data.borrow_mut()[..])?;
+    } else {
+        vault_data.serialize(&mut &mut vault_info.data.borrow_mut()[..])?;
+    }
+    Ok(())
+}
+
+// Signer seeds of the vault PDA, see `vault_seeds`
+fn vault_signer_seeds<'a>(vault_id: &'a [u8; 8], vault_bump: &'a [u8; 1]) -> Vec<&'a [u8]> {
+    [&vault_seeds(vault_id)[..], &[&vault_bump[..]]].concat()
+}
+
+fn assert_admin(vault_data: &ContractData, payer: &AccountInfo) -> ProgramResult {
+    if *payer.key != vault_data.admin || !payer.is_signer {
+        return Err(StakingError::Unauthorized.into());
+    }
+    Ok(())
+}
+
+fn assert_reward_accounts(
+    vault_data: &ContractData,
+    payer: &AccountInfo,
+    vault_info: &AccountInfo,
+    payer_reward_holder_info: &AccountInfo,
+    vault_reward_holder_info: &AccountInfo,
+    reward_mint_info: &AccountInfo,
+) -> ProgramResult {
+    if vault_data.reward_mint != *reward_mint_info.key {
+        return Err(StakingError::WrongRewardMint.into());
+    }
+
+    if spl_associated_token_account::get_associated_token_address(payer.key, &vault_data.reward_mint)
+        != *payer_reward_holder_info.key
+    {
+        return Err(StakingError::WrongPayerRewardHolder.into());
+    }
+
+    if spl_associated_token_account::get_associated_token_address(vault_info.key, &vault_data.reward_mint)
+        != *vault_reward_holder_info.key
+    {
+        return Err(StakingError::WrongVaultRewardHolder.into());
+    }
+    Ok(())
+}
+
+fn assert_nft_holders(
+    payer: &AccountInfo,
+    vault_info: &AccountInfo,
+    nft_info: &AccountInfo,
+    payer_nft_holder_info: &AccountInfo,
+    vault_nft_holder_info: &AccountInfo,
+) -> ProgramResult {
+    if spl_associated_token_account::get_associated_token_address(payer.key, nft_info.key)
+        != *payer_nft_holder_info.key
+    {
+        return Err(StakingError::WrongPayerNftHolder.into());
+    }
+
+    if spl_associated_token_account::get_associated_token_address(vault_info.key, nft_info.key)
+        != *vault_nft_holder_info.key
+    {
+        return Err(StakingError::WrongVaultNftHolder.into());
+    }
+    Ok(())
+}
+
+// Stats PDAs are created on first use, vaults and stakers from before they existed pick them up there.
+// The staker stats PDA is an optional trailing account.
+#[allow(clippy::too_many_arguments)]
+fn load_stats<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    vault_info: &AccountInfo<'a>,
+    stats_info: &AccountInfo<'a>,
+    staker_stats_info: Option<&AccountInfo<'a>>,
+    system_program: &AccountInfo<'a>,
+    rent: &Rent,
+) -> Result<(VaultStats, Option<StakerStats>), ProgramError> {
+    let (stats_address, stats_bump) = find_stats_address(program_id, vault_info.key);
+    if stats_address != *stats_info.key {
+        return Err(StakingError::WrongStats.into());
+    }
+
+    let stats = if stats_info.owner != program_id {
+        create_pda_account(
+            payer,
+            stats_info,
+            system_program,
+            rent,
+            VaultStats::LEN,
+            program_id,
+            &[STATS_SEED.as_bytes(), &vault_info.key.to_bytes(), &[stats_bump]],
+        )?;
+        VaultStats::new(*vault_info.key)
+    } else {
+        VaultStats::unpack(&stats_info.data.borrow())?
+    };
+
+    let staker_stats_info = match staker_stats_info {
+        Some(staker_stats_info) => staker_stats_info,
+        None => return Ok((stats, None)),
+    };
+
+    let (staker_stats_address, staker_stats_bump) = find_staker_stats_address(program_id, vault_info.key, payer.key);
+    if staker_stats_address != *staker_stats_info.key {
+        return Err(StakingError::WrongStakerStats.into());
+    }
+
+    let staker_stats = if staker_stats_info.owner != program_id {
+        create_pda_account(
+            payer,
+            staker_stats_info,
+            system_program,
+            rent,
+            StakerStats::LEN,
+            program_id,
+            &[
+                STAKER_SEED.as_bytes(),
+                &vault_info.key.to_bytes(),
+                &payer.key.to_bytes(),
+                &[staker_stats_bump],
+            ],
+        )?;
+        StakerStats::new(*vault_info.key, *payer.key)
+    } else {
+        StakerStats::unpack(&staker_stats_info.data.borrow())?
+    };
+    Ok((stats, Some(staker_stats)))
+}
+
+fn save_stats(
+    stats_info: &AccountInfo,
+    stats: &VaultStats,
+    staker_stats_info: Option<&AccountInfo>,
+    staker_stats: Option<&StakerStats>,
+) -> ProgramResult {
+    stats.serialize(&mut &mut stats_info.data.borrow_mut()[..])?;
+    if let (Some(staker_stats_info), Some(staker_stats)) = (staker_stats_info, staker_stats) {
+        staker_stats.serialize(&mut &mut staker_stats_info.data.borrow_mut()[..])?;
+    }
+    Ok(())
+}
+
+// A fungible token with a verified creator could otherwise be staked once per unit
+fn assert_nft_mint(mint: &AccountInfo, master_edition_info: &AccountInfo) -> ProgramResult {
+    if *mint.owner != spl_token::id() {
+        return Err(StakingError::InvalidMint.into());
+    }
+    let mint_data = spl_token::state::Mint::unpack(&mint.data.borrow()).map_err(|_| StakingError::InvalidMint)?;
+    if mint_data.supply != 1 || mint_data.decimals != 0 {
+        return Err(StakingError::NotAnNft.into());
+    }
+
+    if find_master_edition_address(mint.key) != *master_edition_info.key
+        || *master_edition_info.owner != spl_token_metadata::id()
+    {
+        return Err(StakingError::WrongMasterEdition.into());
+    }
+    // print editions live at the same PDA, only master editions are accepted
+    let edition_key = master_edition_info.data.borrow().first().copied();
+    if edition_key != Some(Key::MasterEditionV1 as u8) && edition_key != Some(Key::MasterEditionV2 as u8) {
+        return Err(StakingError::WrongMasterEdition.into());
+    }
+    Ok(())
+}
+
+// Resolves the whitelist entry of the nft's collection, None once the collection was removed.
+fn load_collection_rate(
+    program_id: &Pubkey,
+    vault_info: &AccountInfo,
+    nft_info: &AccountInfo,
+    metadata_info: &AccountInfo,
+    whitelist_info: &AccountInfo,
+) -> Result<Option<RateData>, ProgramError> {
+    if find_metadata_address(nft_info.key) != *metadata_info.key {
+        return Err(StakingError::WrongMetadata.into());
+    }
+
+    // from_account_info indexes the first byte, an empty account would panic
+    if metadata_info.data_is_empty() {
+        return Err(StakingError::InvalidMetadata.into());
+    }
+    let metadata = spl_token_metadata::state::Metadata::from_account_info(metadata_info)
+        .map_err(|_| StakingError::InvalidMetadata)?;
+    let first_creator = metadata.data.creators.as_ref().and_then(|creators| creators.first());
+    let collection = load_metadata_collection(&metadata, metadata_info)?;
+    if first_creator.is_none() && collection.is_none() {
+        return Err(StakingError::NoCreators.into());
+    }
+
+    // the whitelist PDA is keyed by either the first creator or the collection mint
+    let whitelisted_by =
+        |key: &Pubkey| find_whitelist_address(program_id, vault_info.key, key).0 == *whitelist_info.key;
+    let first_creator = first_creator.filter(|creator| whitelisted_by(&creator.address));
+    let collection = collection.filter(|collection| whitelisted_by(&collection.key));
+    if first_creator.is_none() && collection.is_none() {
+        return Err(StakingError::WrongWhitelist.into());
+    }
+
+    if whitelist_info.owner != program_id {
+        return Ok(None);
+    }
+
+    let rate_data = RateData::unpack_legacy(&whitelist_info.data.borrow())?;
+    match rate_data.mode {
+        VerificationMode::FirstCreator => {
+            if !first_creator.ok_or(StakingError::WrongWhitelist)?.verified {
+                return Err(StakingError::CreatorNotVerified.into());
+            }
+        }
+        VerificationMode::Collection => {
+            if !collection.ok_or(StakingError::WrongWhitelist)?.verified {
+                return Err(StakingError::CollectionNotVerified.into());
+            }
+        }
+    }
+    Ok(Some(rate_data))
+}
+
+// Newer Metaplex metadata appends token_standard and collection after the fields
+// spl-token-metadata 0.0.1 knows, older accounts are zero padded there
+fn load_metadata_collection(
+    metadata: &spl_token_metadata::state::Metadata,
+    metadata_info: &AccountInfo,
+) -> Result<Option<MetadataCollection>, ProgramError> {
+    let known_len = metadata.try_to_vec()?.len();
+    let data = metadata_info.data.borrow();
+    let mut trailing = data.get(known_len..).unwrap_or(&[]);
+    // anything unreadable there means no collection, first creator whitelists keep working
+    Ok(<(Option<u8>, Option<MetadataCollection>)>::deserialize(&mut trailing)
+        .ok()
+        .and_then(|(_token_standard, collection)| collection))
+}
+
+fn load_whitelist(
+    program_id: &Pubkey,
+    vault_info: &AccountInfo,
+    whitelist_key_info: &AccountInfo,
+    whitelist_info: &AccountInfo,
+) -> Result<RateData, ProgramError> {
+    let (whitelist_address, _whitelist_bump) =
+        find_whitelist_address(program_id, vault_info.key, whitelist_key_info.key);
+    if whitelist_address != *whitelist_info.key {
+        return Err(StakingError::WrongWhitelist.into());
+    }
+
+    if whitelist_info.owner != program_id {
+        return Err(StakingError::NotWhitelisted.into());
+    }
+
+    Ok(RateData::unpack_legacy(&whitelist_info.data.borrow())?)
+}
+
+// Whitelists of the deployed program's vault keep their 8 byte layout
+fn save_whitelist(whitelist_info: &AccountInfo, rate_data: &RateData) -> ProgramResult {
+    if whitelist_info.data_len() == RateData::LEGACY_LEN {
+        let legacy = rate_data.to_legacy().ok_or(StakingError::LegacyLayoutTooSmall)?;
+        legacy.serialize(&mut &mut whitelist_info.data.borrow_mut()[..])?;
+    } else {
+        rate_data.serialize(&mut &mut whitelist_info.data.borrow_mut()[..])?;
+    }
+    Ok(())
+}
+
+// The staker stats PDA trails the nft groups of a batch when it is passed
+fn split_staker_stats<'a, 'b>(
+    accounts: &'b [AccountInfo<'a>],
+    group_len: usize,
+) -> (&'b [AccountInfo<'a>], Option<&'b AccountInfo<'a>>) {
+    match accounts.split_last() {
+        Some((staker_stats_info, nft_accounts)) if accounts.len() % group_len == 1 => {
+            (nft_accounts, Some(staker_stats_info))
+        }
+        _ => (accounts, None),
+    }
+}
+
+// Splits the accounts after the shared ones of a batch instruction into per-nft groups
+fn nft_groups<'a, 'b>(
+    accounts: &'b [AccountInfo<'a>],
+    group_len: usize,
+) -> Result<Vec<NftAccounts<'a, 'b>>, ProgramError> {
+    let groups = accounts.chunks_exact(group_len);
+    if accounts.is_empty() || !groups.remainder().is_empty() {
+        return Err(StakingError::InvalidNftAccounts.into());
+    }
+    Ok(groups
+        .map(|group| NftAccounts {
+            mint: &group[0],
+            metadata_info: &group[1],
+            master_edition_info: &group[2],
+            payer_nft_holder_info: &group[3],
+            vault_nft_holder_info: &group[4],
+            stake_info: &group[5],
+            whitelist_info: &group[6],
+            boost_info: Some(&group[7]),
+        })
+        .collect())
+}
+
+// Boost of the mint in bps, `None` when the boost PDA was not passed. A mint the admin never
+// boosted earns the plain price
+fn load_boost(
+    program_id: &Pubkey,
+    vault_info: &AccountInfo,
+    nft_info: &AccountInfo,
+    boost_info: Option<&AccountInfo>,
+) -> Result<Option<u16>, ProgramError> {
+    let boost_info = match boost_info {
+        Some(boost_info) => boost_info,
+        None => return Ok(None),
+    };
+
+    let (boost_address, _boost_bump) = find_boost_address(program_id, vault_info.key, nft_info.key);
+    if boost_address != *boost_info.key {
+        return Err(StakingError::WrongBoost.into());
+    }
+
+    if boost_info.owner != program_id {
+        return Ok(Some(BPS_DENOMINATOR as u16));
+    }
+    Ok(Some(BoostData::unpack(&boost_info.data.borrow())?.multiplier_bps))
+}
+
+fn load_active_stake(
+    program_id: &Pubkey,
+    stake_info: &AccountInfo,
+    nft_info: &AccountInfo,
+    payer: &AccountInfo,
+    vault_info: &AccountInfo,
+) -> Result<StakeData, ProgramError> {
+    let (stake_address, _stake_bump) = find_stake_address(program_id, nft_info.key);
+    if stake_address != *stake_info.key {
+        return Err(StakingError::WrongStakeData.into());
+    }
+
+    let stake_data = StakeData::unpack(&stake_info.data.borrow())?;
+
+    if !stake_data.active {
+        return Err(StakingError::StakeInactive.into());
+    }
+
+    if stake_data.staker != *payer.key {
+        return Err(StakingError::Unauthorized.into());
+    }
+
+    if stake_data.vault != *vault_info.key {
+        return Err(StakingError::WrongStakeVault.into());
+    }
+    Ok(stake_data)
+}
+
+// Funds, allocates and assigns a PDA to this program
+fn create_pda_account<'a>(
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    rent: &Rent,
+    size: usize,
+    program_id: &Pubkey,
+    seeds: &[&[u8]],
+) -> ProgramResult {
+    let required_lamports = rent
+        .minimum_balance(size)
+        .max(1)
+        .saturating_sub(account.lamports());
+    invoke(
+        &system_instruction::transfer(payer.key, account.key, required_lamports),
+        &[payer.clone(), account.clone(), system_program.clone()],
+    )?;
+    invoke_signed(
+        &system_instruction::allocate(account.key, size as u64),
+        &[account.clone(), system_program.clone()],
+        &[seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(account.key, program_id),
+        &[account.clone(), system_program.clone()],
+        &[seeds],
+    )
+}
+
+
+#[allow(clippy::too_many_arguments)]
+fn create_ata_if_missing<'a>(
+    payer: &AccountInfo<'a>,
+    wallet: &AccountInfo<'a>,
+    ata: &AccountInfo<'a>,
+    mint: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    rent_info: &AccountInfo<'a>,
+    assoc_program: &AccountInfo<'a>,
+) -> ProgramResult {
+    if ata.owner == token_program.key {
+        return Ok(());
+    }
+    invoke(
+        &spl_associated_token_account::create_associated_token_account(payer.key, wallet.key, mint.key),
+        &[
+            payer.clone(),
+            ata.clone(),
+            wallet.clone(),
+            mint.clone(),
+            system_program.clone(),
+            token_program.clone(),
+            rent_info.clone(),
+            assoc_program.clone(),
+        ],
+    )
+}
+
+fn transfer_from_vault<'a>(
+    token_program: &AccountInfo<'a>,
+    source: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    vault_info: &AccountInfo<'a>,
+    amount: u64,
+    vault_seeds: &[&[u8]],
+) -> ProgramResult {
+    invoke_signed(
+        &spl_token::instruction::transfer(token_program.key, source.key, destination.key, vault_info.key, &[], amount)?,
+        &[source.clone(), destination.clone(), vault_info.clone(), token_program.clone()],
+        &[vault_seeds],
+    )
+}
+
+// FreezeDelegatedAccount and ThawDelegatedAccount of the token metadata program,
+// spl-token-metadata 0.0.1 predates them so the instructions are built by hand
+const FREEZE_DELEGATED_ACCOUNT: u8 = 26;
+const THAW_DELEGATED_ACCOUNT: u8 = 27;
+
+fn delegated_account_instruction(
+    tag: u8,
+    delegate: &Pubkey,
+    token_account: &Pubkey,
+    edition: &Pubkey,
+    mint: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: spl_token_metadata::id(),
+        accounts: vec![
+            AccountMeta::new_readonly(*delegate, true),
+            AccountMeta::new(*token_account, false),
+            AccountMeta::new_readonly(*edition, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: vec![tag],
+    }
+}
+
+// Thaws an nft staked in the staker's wallet and drops the vault's delegation
+#[allow(clippy::too_many_arguments)]
+fn thaw_nft<'a>(
+    payer: &AccountInfo<'a>,
+    nft_info: &AccountInfo<'a>,
+    payer_nft_holder_info: &AccountInfo<'a>,
+    master_edition_info: &AccountInfo<'a>,
+    vault_info: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    metadata_program: &AccountInfo<'a>,
+    vault_seeds: &[&[u8]],
+) -> ProgramResult {
+    invoke_signed(
+        &delegated_account_instruction(
+            THAW_DELEGATED_ACCOUNT,
+            vault_info.key,
+            payer_nft_holder_info.key,
+            master_edition_info.key,
+            nft_info.key,
+        ),
+        &[
+            vault_info.clone(),
+            payer_nft_holder_info.clone(),
+            master_edition_info.clone(),
+            nft_info.clone(),
+            token_program.clone(),
+            metadata_program.clone(),
+        ],
+        &[vault_seeds],
+    )?;
+    invoke(
+        &spl_token::instruction::revoke(token_program.key, payer_nft_holder_info.key, payer.key, &[])?,
+        &[payer_nft_holder_info.clone(), payer.clone(), token_program.clone()],
+    )
+}
+
+// Sends the staked nft back to the staker and closes the vault's token account
+#[allow(clippy::too_many_arguments)]
+fn return_nft<'a>(
+    payer: &AccountInfo<'a>,
+    nft_info: &AccountInfo<'a>,
+    payer_nft_holder_info: &AccountInfo<'a>,
+    vault_nft_holder_info: &AccountInfo<'a>,
+    vault_info: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    rent_info: &AccountInfo<'a>,
+    assoc_program: &AccountInfo<'a>,
+    vault_seeds: &[&[u8]],
+) -> ProgramResult {
+    create_ata_if_missing(
+        payer,
+        payer,
+        payer_nft_holder_info,
+        nft_info,
+        system_program,
+        token_program,
+        rent_info,
+        assoc_program,
+    )?;
+    transfer_from_vault(token_program, vault_nft_holder_info, payer_nft_holder_info, vault_info, 1, vault_seeds)?;
+    invoke_signed(
+        &spl_token::instruction::close_account(
+            token_program.key,
+            vault_nft_holder_info.key,
+            payer.key,
+            vault_info.key,
+            &[],
+        )?,
+        &[vault_nft_holder_info.clone(), payer.clone(), vault_info.clone(), token_program.clone()],
+        &[vault_seeds],
+    )
+}
@@ -0,0 +1,522 @@
+use std::convert::TryFrom;
+
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
+use solana_program::{msg, pubkey::Pubkey};
+
+use crate::error::StakingError;
+
+pub const VAULT_SEED: &str = "vault";
+pub const WHITELIST_SEED: &str = "whitelist";
+pub const STATS_SEED: &str = "stats";
+pub const STAKER_SEED: &str = "staker";
+pub const BOOST_SEED: &str = "boost";
+
+// fixed point scale of the vault reward index
+pub const REWARD_INDEX_PRECISION: u128 = 1_000_000_000_000;
+
+// lock tier multipliers are in basis points, 10_000 pays the plain whitelist price
+pub const BPS_DENOMINATOR: u64 = 10_000;
+pub const MAX_LOCK_TIERS: usize = 4;
+// caps a mint's boost at 5x
+pub const MAX_BOOST_BPS: u16 = 50_000;
+
+// The deployed program had a single vault at ["vault"] with its whitelists at ["whitelist", key],
+// read in place it gets this vault_id
+pub const LEGACY_VAULT_ID: u64 = u64::MAX;
+
+// Admin and reward mint the deployed program had hardcoded
+pub mod legacy_admin {
+    solana_program::declare_id!("Ek6Vqf4cCq6zXAp9TwSqeAbQXm8Eo3Y8DV7abbJYntwv");
+}
+
+pub mod legacy_reward_mint {
+    solana_program::declare_id!("Aoz9EBZPZ8oQHnuV8UY5bCV87xJ5DpwFcy84TrRWBCzp");
+}
+
+// Vault PDA seeds without the bump, `vault_id` in little endian
+pub fn vault_seeds(vault_id: &[u8; 8]) -> Vec<&[u8]> {
+    if *vault_id == LEGACY_VAULT_ID.to_le_bytes() {
+        vec![VAULT_SEED.as_bytes()]
+    } else {
+        vec![VAULT_SEED.as_bytes(), vault_id]
+    }
+}
+
+pub fn find_vault_address(program_id: &Pubkey, vault_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&vault_seeds(&vault_id.to_le_bytes()), program_id)
+}
+
+// Whitelist PDA seeds without the bump
+pub fn whitelist_seeds<'a>(program_id: &Pubkey, vault: &'a Pubkey, whitelist_key: &'a Pubkey) -> Vec<&'a [u8]> {
+    if *vault == find_vault_address(program_id, LEGACY_VAULT_ID).0 {
+        vec![WHITELIST_SEED.as_bytes(), whitelist_key.as_ref()]
+    } else {
+        vec![WHITELIST_SEED.as_bytes(), vault.as_ref(), whitelist_key.as_ref()]
+    }
+}
+
+pub fn find_whitelist_address(program_id: &Pubkey, vault: &Pubkey, whitelist_key: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&whitelist_seeds(program_id, vault, whitelist_key), program_id)
+}
+
+pub fn find_stake_address(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[&mint.to_bytes()], program_id)
+}
+
+pub fn find_stats_address(program_id: &Pubkey, vault: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[STATS_SEED.as_bytes(), &vault.to_bytes()], program_id)
+}
+
+pub fn find_staker_stats_address(program_id: &Pubkey, vault: &Pubkey, staker: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[STAKER_SEED.as_bytes(), &vault.to_bytes(), &staker.to_bytes()], program_id)
+}
+
+pub fn find_boost_address(program_id: &Pubkey, vault: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[BOOST_SEED.as_bytes(), &vault.to_bytes(), &mint.to_bytes()], program_id)
+}
+
+pub fn find_metadata_address(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            spl_token_metadata::state::PREFIX.as_bytes(),
+            &spl_token_metadata::ID.to_bytes(),
+            &mint.to_bytes(),
+        ],
+        &spl_token_metadata::ID,
+    )
+    .0
+}
+
+// Leading byte of every account, followed by the layout version
+#[derive(Clone, Copy, Debug, PartialEq, BorshDeserialize, BorshSerialize, BorshSchema)]
+pub enum AccountKey {
+    Uninitialized,
+    Vault,
+    Stake,
+    Whitelist,
+    Stats,
+    StakerStats,
+    Boost,
+}
+
+// Accounts written by the deployed program have no key and version bytes and are recognised by their length
+fn check_header(data: &[u8], key: AccountKey, version: u8, legacy_len: Option<usize>) -> Result<(), StakingError> {
+    if Some(data.len()) == legacy_len {
+        return Err(StakingError::LegacyAccount);
+    }
+    match data {
+        [k, _, ..] if *k != key as u8 => Err(StakingError::WrongAccountType),
+        [_, v, ..] if *v != version => Err(StakingError::UnsupportedVersion),
+        [_, _, ..] => Ok(()),
+        _ => Err(StakingError::WrongAccountType),
+    }
+}
+
+pub fn find_master_edition_address(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            spl_token_metadata::state::PREFIX.as_bytes(),
+            &spl_token_metadata::ID.to_bytes(),
+            &mint.to_bytes(),
+            spl_token_metadata::state::EDITION.as_bytes(),
+        ],
+        &spl_token_metadata::ID,
+    )
+    .0
+}
+
+// How Stake, Claim and Unstake tie an nft to a whitelist PDA
+#[derive(Clone, Copy, Debug, PartialEq, BorshDeserialize, BorshSerialize, BorshSchema)]
+pub enum VerificationMode {
+    // whitelist is keyed by the verified first creator, usually the candy machine
+    FirstCreator,
+    // whitelist is keyed by the verified Metaplex collection mint
+    Collection,
+}
+
+// Collection field of newer Metaplex metadata, spl-token-metadata 0.0.1 predates it
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct MetadataCollection {
+    pub verified: bool,
+    pub key: Pubkey,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, BorshDeserialize, BorshSerialize, BorshSchema)]
+pub enum RewardMode {
+    // rewards are paid for whole reward periods only
+    Periodic,
+    // rewards accrue every second, fractions of a token are carried in StakeData
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, BorshDeserialize, BorshSerialize, BorshSchema)]
+pub enum CustodyMode {
+    // the nft is moved into an ATA owned by the vault PDA
+    Vault,
+    // the nft stays in the staker's ATA, delegated to the vault PDA and frozen
+    Wallet,
+}
+
+// A tier with a zero multiplier is unused
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshDeserialize, BorshSerialize, BorshSchema)]
+pub struct LockTier {
+    pub duration: u64,
+    pub multiplier_bps: u16,
+}
+
+// Layouts of the deployed program
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct LegacyStakeData {
+    pub timestamp: u64,
+    pub staker: Pubkey,
+    pub active: bool,
+}
+
+impl LegacyStakeData {
+    pub const LEN: usize = 8 + 32 + 1;
+}
+
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct LegacyContractData {
+    pub min_period: u64,
+    pub reward_period: u64,
+}
+
+impl LegacyContractData {
+    pub const LEN: usize = 8 + 8;
+}
+
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct LegacyRateData {
+    pub price: u64,
+}
+
+impl LegacyRateData {
+    pub const LEN: usize = 8;
+}
+
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize, BorshSchema)]
+pub struct StakeData {
+    pub key: AccountKey,
+    pub version: u8,
+    pub timestamp: u64,
+    pub staker: Pubkey,
+    pub active: bool,
+    pub vault: Pubkey,
+    pub claimed_at: u64,
+    pub reward_index: u128,
+    pub reward_carry: u64,
+    pub custody: CustodyMode,
+    // unstaking is rejected before this timestamp
+    pub lock_end: u64,
+    // copied from the lock tier so later tier changes leave running stakes alone
+    pub multiplier_bps: u16,
+    // copied from the mint's boost PDA for the same reason
+    pub boost_bps: u16,
+}
+
+impl StakeData {
+    pub const VERSION: u8 = 1;
+    pub const LEGACY_LEN: usize = LegacyStakeData::LEN;
+    pub const LEN: usize = 1 + 1 + 8 + 32 + 1 + 32 + 8 + 16 + 8 + 1 + 8 + 2 + 2;
+
+    pub fn unpack(data: &[u8]) -> Result<Self, StakingError> {
+        check_header(data, AccountKey::Stake, Self::VERSION, Some(Self::LEGACY_LEN))?;
+        Self::try_from_slice(data).map_err(|_| StakingError::InvalidStakeData)
+    }
+
+    // Pays out everything accrued since the stake's last checkpoint and moves the checkpoint forward,
+    // the mint's boost applies on top of the lock multiplier
+    pub fn settle_reward(&mut self, vault_data: &ContractData, price: u64, now: u64) -> Result<u64, StakingError> {
+        let multiplier_bps = self.multiplier_bps as u128 * self.boost_bps as u128;
+        let denominator = BPS_DENOMINATOR as u128 * BPS_DENOMINATOR as u128;
+        let reward_index = vault_data.current_reward_index(now)?;
+        // a skewed clock can put the index behind the stake's snapshot, nothing accrued then
+        let accrued_index = reward_index.saturating_sub(self.reward_index);
+        self.claimed_at = now;
+        let accrued_index = match vault_data.reward_mode {
+            RewardMode::Periodic => {
+                let periods = accrued_index / REWARD_INDEX_PRECISION;
+                msg!("periods passed {:?}", periods);
+                // the unfinished period keeps accruing
+                periods * REWARD_INDEX_PRECISION
+            }
+            RewardMode::Linear => accrued_index,
+        };
+        // both modes keep the fraction of a token for the next claim, a carry left by Linear
+        // is still paid out after the vault switched to Periodic
+        let accrued = accrued_index
+            .checked_mul(price as u128)
+            .and_then(|accrued| accrued.checked_mul(multiplier_bps))
+            .map(|accrued| accrued / denominator)
+            .and_then(|accrued| accrued.checked_add(self.reward_carry as u128))
+            .ok_or(StakingError::MathOverflow)?;
+        self.reward_index += accrued_index;
+        self.reward_carry = (accrued % REWARD_INDEX_PRECISION) as u64;
+        u64::try_from(accrued / REWARD_INDEX_PRECISION).map_err(|_| StakingError::MathOverflow)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize, BorshSchema)]
+pub struct ContractData {
+    pub key: AccountKey,
+    pub version: u8,
+    pub vault_id: u64,
+    pub min_period: u64,
+    pub reward_period: u64,
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+    pub reward_mint: Pubkey,
+    pub reward_mode: RewardMode,
+    // reward periods elapsed since the vault was created, scaled by REWARD_INDEX_PRECISION
+    pub reward_index: u128,
+    pub reward_index_updated_at: u64,
+    // blocks Stake and Claim, Unstake returns nfts without rewards
+    pub paused: bool,
+    pub lock_tiers: [LockTier; MAX_LOCK_TIERS],
+    // lets Unstake skip min_period, the penalty share of the reward stays in the vault
+    pub early_exit: bool,
+    pub early_exit_penalty_bps: u16,
+}
+
+impl ContractData {
+    pub const VERSION: u8 = 1;
+    pub const LEGACY_LEN: usize = LegacyContractData::LEN;
+    pub const LEN: usize = 1 + 1 + 8 + 8 + 8 + 32 + 32 + 32 + 1 + 16 + 8 + 1 + (8 + 2) * MAX_LOCK_TIERS + 1 + 2;
+
+    pub fn unpack(data: &[u8]) -> Result<Self, StakingError> {
+        check_header(data, AccountKey::Vault, Self::VERSION, Some(Self::LEGACY_LEN))?;
+        Self::try_from_slice(data).map_err(|_| StakingError::InvalidVaultData)
+    }
+
+    // Also reads the deployed program's vault, which keeps its 16 byte layout
+    pub fn unpack_legacy(data: &[u8]) -> Result<Self, StakingError> {
+        match Self::unpack(data) {
+            Err(StakingError::LegacyAccount) => Self::from_legacy(data).ok_or(StakingError::InvalidVaultData),
+            vault_data => vault_data,
+        }
+    }
+
+    // The deployed program's vault pays in whole periods counted from the epoch, its admin and
+    // reward mint were hardcoded
+    pub fn from_legacy(data: &[u8]) -> Option<Self> {
+        if data.len() != Self::LEGACY_LEN {
+            return None;
+        }
+        let legacy = LegacyContractData::try_from_slice(data).ok()?;
+        Some(Self {
+            key: AccountKey::Vault,
+            version: Self::VERSION,
+            vault_id: LEGACY_VAULT_ID,
+            min_period: legacy.min_period,
+            reward_period: legacy.reward_period,
+            admin: legacy_admin::id(),
+            pending_admin: Pubkey::default(),
+            reward_mint: legacy_reward_mint::id(),
+            reward_mode: RewardMode::Periodic,
+            reward_index: 0,
+            reward_index_updated_at: 0,
+            paused: false,
+            lock_tiers: [LockTier::default(); MAX_LOCK_TIERS],
+            early_exit: false,
+            early_exit_penalty_bps: 0,
+        })
+    }
+
+    // The 16 byte layout, `None` when the vault holds anything the deployed program had no room for
+    pub fn to_legacy(&self) -> Option<LegacyContractData> {
+        let legacy = LegacyContractData {
+            min_period: self.min_period,
+            reward_period: self.reward_period,
+        };
+        let unchanged = Self::from_legacy(&legacy.try_to_vec().ok()?).as_ref() == Some(self);
+        unchanged.then_some(legacy)
+    }
+
+    // Share of an early unstake's reward that is withheld
+    pub fn early_exit_penalty(&self, reward: u64) -> Result<u64, StakingError> {
+        u64::try_from(reward as u128 * self.early_exit_penalty_bps as u128 / BPS_DENOMINATOR as u128)
+            .map_err(|_| StakingError::MathOverflow)
+    }
+
+    // `None` stakes without a lock at the plain price
+    pub fn lock_tier(&self, lock_tier: Option<u8>) -> Result<LockTier, StakingError> {
+        let lock_tier = match lock_tier {
+            Some(index) => self.lock_tiers.get(index as usize).copied(),
+            None => Some(LockTier {
+                duration: 0,
+                multiplier_bps: BPS_DENOMINATOR as u16,
+            }),
+        };
+        lock_tier
+            .filter(|lock_tier| lock_tier.multiplier_bps != 0)
+            .ok_or(StakingError::InvalidLockTier)
+    }
+
+    // Vault reward index at `now`, reward_period changes are folded into the stored checkpoint
+    pub fn current_reward_index(&self, now: u64) -> Result<u128, StakingError> {
+        let elapsed = now.saturating_sub(self.reward_index_updated_at) as u128;
+        (elapsed * REWARD_INDEX_PRECISION)
+            .checked_div(self.reward_period as u128)
+            .and_then(|accrued| self.reward_index.checked_add(accrued))
+            .ok_or(StakingError::MathOverflow)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize, BorshSchema)]
+pub struct RateData {
+    pub key: AccountKey,
+    pub version: u8,
+    pub price: u64,
+    // blocks new stakes, running stakes can still be claimed and unstaked
+    pub disabled: bool,
+    pub mode: VerificationMode,
+}
+
+impl RateData {
+    pub const VERSION: u8 = 1;
+    pub const LEGACY_LEN: usize = LegacyRateData::LEN;
+    pub const LEN: usize = 1 + 1 + 8 + 1 + 1;
+
+    pub fn unpack(data: &[u8]) -> Result<Self, StakingError> {
+        check_header(data, AccountKey::Whitelist, Self::VERSION, Some(Self::LEGACY_LEN))?;
+        Self::try_from_slice(data).map_err(|_| StakingError::InvalidRateData)
+    }
+
+    // Also reads whitelists of the deployed program's vault, which keep their 8 byte layout
+    pub fn unpack_legacy(data: &[u8]) -> Result<Self, StakingError> {
+        match Self::unpack(data) {
+            Err(StakingError::LegacyAccount) => Self::from_legacy(data).ok_or(StakingError::InvalidRateData),
+            rate_data => rate_data,
+        }
+    }
+
+    // The deployed program's whitelists all verified by first creator
+    pub fn from_legacy(data: &[u8]) -> Option<Self> {
+        if data.len() != Self::LEGACY_LEN {
+            return None;
+        }
+        Some(Self {
+            key: AccountKey::Whitelist,
+            version: Self::VERSION,
+            price: LegacyRateData::try_from_slice(data).ok()?.price,
+            disabled: false,
+            mode: VerificationMode::FirstCreator,
+        })
+    }
+
+    // The 8 byte layout, `None` when the whitelist holds anything the deployed program had no room for
+    pub fn to_legacy(&self) -> Option<LegacyRateData> {
+        let legacy = LegacyRateData { price: self.price };
+        let unchanged = Self::from_legacy(&legacy.try_to_vec().ok()?).as_ref() == Some(self);
+        unchanged.then_some(legacy)
+    }
+}
+
+// Per vault totals kept up to date by every instruction that stakes, unstakes or pays rewards,
+// UnstakeLegacy leaves them alone
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize, BorshSchema)]
+pub struct VaultStats {
+    pub key: AccountKey,
+    pub version: u8,
+    pub vault: Pubkey,
+    pub total_staked: u64,
+    pub total_rewards: u64,
+}
+
+impl VaultStats {
+    pub const VERSION: u8 = 1;
+    pub const LEN: usize = 1 + 1 + 32 + 8 + 8;
+
+    pub fn new(vault: Pubkey) -> Self {
+        Self {
+            key: AccountKey::Stats,
+            version: Self::VERSION,
+            vault,
+            total_staked: 0,
+            total_rewards: 0,
+        }
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, StakingError> {
+        check_header(data, AccountKey::Stats, Self::VERSION, None)?;
+        Self::try_from_slice(data).map_err(|_| StakingError::InvalidStatsData)
+    }
+
+    // the staker stats are optional, without them only the vault totals move
+    pub fn record_stake(&mut self, staker_stats: Option<&mut StakerStats>) -> Result<(), StakingError> {
+        self.total_staked = self.total_staked.checked_add(1).ok_or(StakingError::MathOverflow)?;
+        if let Some(staker_stats) = staker_stats {
+            staker_stats.active_stakes = staker_stats.active_stakes.checked_add(1).ok_or(StakingError::MathOverflow)?;
+        }
+        Ok(())
+    }
+
+    // stakes from before the stats accounts existed were never counted, the counters saturate at zero
+    pub fn record_unstake(&mut self, staker_stats: Option<&mut StakerStats>) {
+        self.total_staked = self.total_staked.saturating_sub(1);
+        if let Some(staker_stats) = staker_stats {
+            staker_stats.active_stakes = staker_stats.active_stakes.saturating_sub(1);
+        }
+    }
+
+    pub fn record_reward(&mut self, staker_stats: Option<&mut StakerStats>, reward: u64) -> Result<(), StakingError> {
+        self.total_rewards = self.total_rewards.checked_add(reward).ok_or(StakingError::MathOverflow)?;
+        if let Some(staker_stats) = staker_stats {
+            staker_stats.lifetime_rewards =
+                staker_stats.lifetime_rewards.checked_add(reward).ok_or(StakingError::MathOverflow)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize, BorshSchema)]
+pub struct StakerStats {
+    pub key: AccountKey,
+    pub version: u8,
+    pub vault: Pubkey,
+    pub staker: Pubkey,
+    pub active_stakes: u64,
+    pub lifetime_rewards: u64,
+}
+
+impl StakerStats {
+    pub const VERSION: u8 = 1;
+    pub const LEN: usize = 1 + 1 + 32 + 32 + 8 + 8;
+
+    pub fn new(vault: Pubkey, staker: Pubkey) -> Self {
+        Self {
+            key: AccountKey::StakerStats,
+            version: Self::VERSION,
+            vault,
+            staker,
+            active_stakes: 0,
+            lifetime_rewards: 0,
+        }
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, StakingError> {
+        check_header(data, AccountKey::StakerStats, Self::VERSION, None)?;
+        Self::try_from_slice(data).map_err(|_| StakingError::InvalidStatsData)
+    }
+}
+
+// Per mint reward multiplier set by the admin, a mint without one earns the plain price
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize, BorshSchema)]
+pub struct BoostData {
+    pub key: AccountKey,
+    pub version: u8,
+    pub vault: Pubkey,
+    pub mint: Pubkey,
+    pub multiplier_bps: u16,
+}
+
+impl BoostData {
+    pub const VERSION: u8 = 1;
+    pub const LEN: usize = 1 + 1 + 32 + 32 + 2;
+
+    pub fn unpack(data: &[u8]) -> Result<Self, StakingError> {
+        check_header(data, AccountKey::Boost, Self::VERSION, None)?;
+        Self::try_from_slice(data).map_err(|_| StakingError::InvalidBoostData)
+    }
+}
@@ -0,0 +1,2087 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{Instruction, InstructionError},
+    program::invoke_signed,
+    program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    sysvar::clock::Clock,
+};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+use spl_associated_token_account::get_associated_token_address;
+use spl_token_metadata::state::{
+    Creator, Data, Key, MasterEditionV2, Metadata, EDITION, MAX_MASTER_EDITION_LEN, MAX_METADATA_LEN, PREFIX,
+};
+use staking::{
+    error::StakingError,
+    instruction::{self, StakeInstruction},
+    processor::process_instruction,
+    state::{
+        find_boost_address, find_master_edition_address, find_metadata_address, find_stake_address,
+        find_staker_stats_address, find_stats_address, find_vault_address, find_whitelist_address, legacy_admin,
+        legacy_reward_mint, AccountKey, BoostData, ContractData, CustodyMode, LegacyContractData, LegacyRateData,
+        LegacyStakeData, LockTier, MetadataCollection, RateData, RewardMode, StakeData, StakerStats, VaultStats,
+        VerificationMode, LEGACY_VAULT_ID, MAX_BOOST_BPS, MAX_LOCK_TIERS,
+    },
+};
+
+const VAULT_ID: u64 = 7;
+const PRICE: u64 = 10;
+const VAULT_REWARDS: u64 = 1_000_000;
+
+struct Fixture {
+    program_id: Pubkey,
+    staker: Keypair,
+    whitelist_key: Pubkey,
+    nft_mint: Pubkey,
+    // same collection, only staked by the batch tests
+    second_nft_mint: Pubkey,
+    reward_mint: Pubkey,
+}
+
+fn token_account(mint: &Pubkey, owner: &Pubkey, amount: u64) -> Account {
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account {
+        mint: *mint,
+        owner: *owner,
+        amount,
+        delegate: COption::None,
+        state: spl_token::state::AccountState::Initialized,
+        is_native: COption::None,
+        delegated_amount: 0,
+        close_authority: COption::None,
+    }
+    .pack_into_slice(&mut data);
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: spl_token::id(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn mint_account(supply: u64, freeze_authority: COption<Pubkey>) -> Account {
+    let mut data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint {
+        mint_authority: COption::Some(Pubkey::new_unique()),
+        supply,
+        decimals: 0,
+        is_initialized: true,
+        freeze_authority,
+    }
+    .pack_into_slice(&mut data);
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: spl_token::id(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn program_account(owner: &Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: *owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+// Key and version of the current layout with the fields cut off, only deserializing the rest fails
+fn truncated_account(program_id: &Pubkey, key: AccountKey, version: u8) -> Account {
+    program_account(program_id, vec![key as u8, version, 0])
+}
+
+fn metadata_account(mint: &Pubkey, creators: Option<Vec<Creator>>, collection: Option<MetadataCollection>) -> Account {
+    let mut data = Metadata {
+        key: Key::MetadataV1,
+        update_authority: Pubkey::new_unique(),
+        mint: *mint,
+        data: Data {
+            name: "Staked #1".to_string(),
+            symbol: "STK".to_string(),
+            uri: "https://example.com/1.json".to_string(),
+            seller_fee_basis_points: 500,
+            creators,
+        },
+        primary_sale_happened: true,
+        is_mutable: true,
+        edition_nonce: None,
+    }
+    .try_to_vec()
+    .unwrap();
+    // newer Metaplex layout: token_standard, then collection
+    data.extend((None::<u8>, collection).try_to_vec().unwrap());
+    data.resize(MAX_METADATA_LEN, 0);
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: spl_token_metadata::id(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn master_edition_account() -> Account {
+    let mut data = MasterEditionV2 {
+        key: Key::MasterEditionV2,
+        supply: 0,
+        max_supply: Some(0),
+    }
+    .try_to_vec()
+    .unwrap();
+    data.resize(MAX_MASTER_EDITION_LEN, 0);
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: spl_token_metadata::id(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+// Staker holding two nfts of a candy machine collection and a vault reward ATA funded up front
+async fn setup(creator_verified: bool) -> (ProgramTestContext, Fixture) {
+    setup_with_creators(|whitelist_key| {
+        Some(vec![Creator {
+            address: *whitelist_key,
+            verified: creator_verified,
+            share: 100,
+        }])
+    })
+    .await
+}
+
+async fn setup_with_creators(creators: impl FnOnce(&Pubkey) -> Option<Vec<Creator>>) -> (ProgramTestContext, Fixture) {
+    let (program_test, fixture) = program_test_with_metadata(|whitelist_key| (creators(whitelist_key), None));
+    (program_test.start_with_context().await, fixture)
+}
+
+// Nft whose metadata carries a collection keyed by the whitelist key, the first creator is someone else
+async fn setup_with_collection(verified: bool) -> (ProgramTestContext, Fixture) {
+    let (program_test, fixture) = program_test_with_metadata(|whitelist_key| {
+        (
+            Some(vec![Creator {
+                address: Pubkey::new_unique(),
+                verified: true,
+                share: 100,
+            }]),
+            Some(MetadataCollection {
+                verified,
+                key: *whitelist_key,
+            }),
+        )
+    });
+    (program_test.start_with_context().await, fixture)
+}
+
+// Stands in for FreezeDelegatedAccount and ThawDelegatedAccount of the token metadata program, which
+// spl-token-metadata 0.0.1 does not have. Like Metaplex, only the signing delegate of the token account can
+// freeze it and the master edition signs as the mint's freeze authority.
+fn process_token_metadata(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let delegate = &accounts[0];
+    let token_account = &accounts[1];
+    let edition = &accounts[2];
+    let mint = &accounts[3];
+    let token_program = &accounts[4];
+
+    let token = spl_token::state::Account::unpack(&token_account.data.borrow())?;
+    if !delegate.is_signer || token.delegate != COption::Some(*delegate.key) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let edition_seeds = &[PREFIX.as_bytes(), program_id.as_ref(), mint.key.as_ref(), EDITION.as_bytes()];
+    let (edition_address, edition_bump) = Pubkey::find_program_address(edition_seeds, program_id);
+    if edition_address != *edition.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let token_instruction = match data {
+        [26] => spl_token::instruction::freeze_account,
+        [27] => spl_token::instruction::thaw_account,
+        _ => return Err(ProgramError::InvalidInstructionData),
+    };
+    let instruction = token_instruction(token_program.key, token_account.key, mint.key, edition.key, &[])?;
+    invoke_signed(
+        &instruction,
+        &[token_account.clone(), mint.clone(), edition.clone(), token_program.clone()],
+        &[&[PREFIX.as_bytes(), program_id.as_ref(), mint.key.as_ref(), EDITION.as_bytes(), &[edition_bump]]],
+    )
+}
+
+fn program_test_with_verified_creator() -> (ProgramTest, Fixture) {
+    program_test_with_metadata(|whitelist_key| {
+        (
+            Some(vec![Creator {
+                address: *whitelist_key,
+                verified: true,
+                share: 100,
+            }]),
+            None,
+        )
+    })
+}
+
+fn program_test_with_metadata(
+    metadata: impl FnOnce(&Pubkey) -> (Option<Vec<Creator>>, Option<MetadataCollection>),
+) -> (ProgramTest, Fixture) {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::new("staking", program_id, processor!(process_instruction));
+    program_test.add_program("spl_token_metadata", spl_token_metadata::id(), processor!(process_token_metadata));
+
+    let staker = Keypair::new();
+    let whitelist_key = Pubkey::new_unique();
+    let nft_mint = Pubkey::new_unique();
+    let second_nft_mint = Pubkey::new_unique();
+    let reward_mint = Pubkey::new_unique();
+    let (vault, _) = find_vault_address(&program_id, VAULT_ID);
+
+    program_test.add_account(
+        staker.pubkey(),
+        Account {
+            lamports: 1_000_000_000,
+            ..Account::default()
+        },
+    );
+    let (creators, collection) = metadata(&whitelist_key);
+    for mint in &[nft_mint, second_nft_mint] {
+        // Metaplex makes the master edition the freeze authority of every nft mint
+        program_test.add_account(*mint, mint_account(1, COption::Some(find_master_edition_address(mint))));
+        program_test.add_account(
+            get_associated_token_address(&staker.pubkey(), mint),
+            token_account(mint, &staker.pubkey(), 1),
+        );
+        program_test.add_account(
+            find_metadata_address(mint),
+            metadata_account(mint, creators.clone(), collection.clone()),
+        );
+        program_test.add_account(find_master_edition_address(mint), master_edition_account());
+    }
+    program_test.add_account(reward_mint, mint_account(VAULT_REWARDS, COption::None));
+    program_test.add_account(
+        get_associated_token_address(&vault, &reward_mint),
+        token_account(&reward_mint, &vault, VAULT_REWARDS),
+    );
+
+    (
+        program_test,
+        Fixture {
+            program_id,
+            staker,
+            whitelist_key,
+            nft_mint,
+            second_nft_mint,
+            reward_mint,
+        },
+    )
+}
+
+async fn process(
+    context: &mut ProgramTestContext,
+    instruction: Instruction,
+    signers: &[&Keypair],
+) -> Result<(), TransportError> {
+    let recent_blockhash = context.banks_client.get_recent_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        recent_blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await
+}
+
+fn assert_staking_error(result: Result<(), TransportError>, error: StakingError) {
+    assert_eq!(
+        result.unwrap_err().unwrap(),
+        TransactionError::InstructionError(0, InstructionError::Custom(error as u32)),
+    );
+}
+
+// Missing token accounts count as empty, the program only creates reward ATAs when paying
+async fn token_balance(context: &mut ProgramTestContext, address: &Pubkey) -> u64 {
+    match context.banks_client.get_account(*address).await.unwrap() {
+        Some(account) => spl_token::state::Account::unpack(&account.data).unwrap().amount,
+        None => 0,
+    }
+}
+
+async fn generate_vault(context: &mut ProgramTestContext, fixture: &Fixture, min_period: u64) {
+    let instruction = instruction::generate_vault(
+        &fixture.program_id,
+        &context.payer.pubkey(),
+        &fixture.reward_mint,
+        VAULT_ID,
+        min_period,
+        1,
+        RewardMode::Periodic,
+    );
+    process(context, instruction, &[]).await.unwrap();
+}
+
+async fn whitelist(context: &mut ProgramTestContext, fixture: &Fixture) {
+    whitelist_with_mode(context, fixture, VerificationMode::FirstCreator).await;
+}
+
+async fn whitelist_with_mode(context: &mut ProgramTestContext, fixture: &Fixture, mode: VerificationMode) {
+    let instruction = instruction::add_to_whitelist(
+        &fixture.program_id,
+        &context.payer.pubkey(),
+        VAULT_ID,
+        &fixture.whitelist_key,
+        PRICE,
+        mode,
+    );
+    process(context, instruction, &[]).await.unwrap();
+}
+
+fn stake_instruction(fixture: &Fixture) -> Instruction {
+    instruction::stake(
+        &fixture.program_id,
+        &fixture.staker.pubkey(),
+        &fixture.nft_mint,
+        VAULT_ID,
+        &fixture.whitelist_key,
+        None,
+    )
+}
+
+fn unstake_instruction(fixture: &Fixture) -> Instruction {
+    instruction::unstake(
+        &fixture.program_id,
+        &fixture.staker.pubkey(),
+        &fixture.nft_mint,
+        VAULT_ID,
+        &fixture.whitelist_key,
+        &fixture.reward_mint,
+    )
+}
+
+fn claim_instruction(fixture: &Fixture) -> Instruction {
+    instruction::claim(
+        &fixture.program_id,
+        &fixture.staker.pubkey(),
+        &fixture.nft_mint,
+        VAULT_ID,
+        &fixture.whitelist_key,
+        &fixture.reward_mint,
+    )
+}
+
+async fn stake(context: &mut ProgramTestContext, fixture: &Fixture) {
+    process(context, stake_instruction(fixture), &[&fixture.staker]).await.unwrap();
+}
+
+#[tokio::test]
+async fn stake_warp_unstake_pays_rewards() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    stake(&mut context, &fixture).await;
+
+    let (vault, _) = find_vault_address(&fixture.program_id, VAULT_ID);
+    let staker_nft = get_associated_token_address(&fixture.staker.pubkey(), &fixture.nft_mint);
+    let vault_nft = get_associated_token_address(&vault, &fixture.nft_mint);
+    assert_eq!(token_balance(&mut context, &staker_nft).await, 0);
+    assert_eq!(token_balance(&mut context, &vault_nft).await, 1);
+
+    let (stake_address, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    let stake_data = StakeData::try_from_slice(&stake_account.data).unwrap();
+    assert!(stake_data.active);
+    assert_eq!(stake_data.staker, fixture.staker.pubkey());
+    assert_eq!(stake_data.vault, vault);
+
+    let (stats_address, _) = find_stats_address(&fixture.program_id, &vault);
+    let stats_account = context.banks_client.get_account(stats_address).await.unwrap().unwrap();
+    let stats = VaultStats::try_from_slice(&stats_account.data).unwrap();
+    assert_eq!(stats.total_staked, 1);
+
+    context.warp_to_slot(1_000).unwrap();
+    process(&mut context, unstake_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+
+    // reward_period is one second, so every elapsed second pays PRICE
+    let clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    let expected_reward = (clock.unix_timestamp as u64 - stake_data.timestamp) * PRICE;
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &fixture.reward_mint);
+    let vault_reward = get_associated_token_address(&vault, &fixture.reward_mint);
+    assert_eq!(token_balance(&mut context, &staker_reward).await, expected_reward);
+    assert_eq!(token_balance(&mut context, &vault_reward).await, VAULT_REWARDS - expected_reward);
+    assert_eq!(token_balance(&mut context, &staker_nft).await, 1);
+    assert!(context.banks_client.get_account(vault_nft).await.unwrap().is_none());
+
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    assert!(!StakeData::try_from_slice(&stake_account.data).unwrap().active);
+
+    let (stats_address, _) = find_stats_address(&fixture.program_id, &vault);
+    let stats_account = context.banks_client.get_account(stats_address).await.unwrap().unwrap();
+    let stats = VaultStats::try_from_slice(&stats_account.data).unwrap();
+    assert_eq!(stats.total_staked, 0);
+    assert_eq!(stats.total_rewards, expected_reward);
+
+    let (staker_stats_address, _) =
+        find_staker_stats_address(&fixture.program_id, &vault, &fixture.staker.pubkey());
+    let staker_stats_account = context.banks_client.get_account(staker_stats_address).await.unwrap().unwrap();
+    let staker_stats = StakerStats::try_from_slice(&staker_stats_account.data).unwrap();
+    assert_eq!(staker_stats.active_stakes, 0);
+    assert_eq!(staker_stats.lifetime_rewards, expected_reward);
+}
+
+#[tokio::test]
+async fn generate_vault_twice_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+
+    let instruction = instruction::generate_vault(
+        &fixture.program_id,
+        &context.payer.pubkey(),
+        &fixture.reward_mint,
+        VAULT_ID,
+        0,
+        2,
+        RewardMode::Linear,
+    );
+    assert_staking_error(process(&mut context, instruction, &[]).await, StakingError::VaultAlreadyExists);
+}
+
+#[tokio::test]
+async fn generate_vault_with_wrong_address_fails() {
+    let (mut context, fixture) = setup(true).await;
+    let mut instruction = instruction::generate_vault(
+        &fixture.program_id,
+        &context.payer.pubkey(),
+        &fixture.reward_mint,
+        VAULT_ID,
+        0,
+        1,
+        RewardMode::Periodic,
+    );
+    instruction.accounts[2].pubkey = find_vault_address(&fixture.program_id, VAULT_ID + 1).0;
+    assert_staking_error(process(&mut context, instruction, &[]).await, StakingError::WrongVault);
+}
+
+#[tokio::test]
+async fn generate_vault_with_non_mint_reward_fails() {
+    let (mut context, fixture) = setup(true).await;
+    let instruction = instruction::generate_vault(
+        &fixture.program_id,
+        &context.payer.pubkey(),
+        &fixture.staker.pubkey(),
+        VAULT_ID,
+        0,
+        1,
+        RewardMode::Periodic,
+    );
+    assert_staking_error(process(&mut context, instruction, &[]).await, StakingError::InvalidRewardMint);
+}
+
+#[tokio::test]
+async fn generate_vault_with_token_account_reward_fails() {
+    let (mut context, fixture) = setup(true).await;
+    let staker_nft = get_associated_token_address(&fixture.staker.pubkey(), &fixture.nft_mint);
+    let instruction = instruction::generate_vault(
+        &fixture.program_id,
+        &context.payer.pubkey(),
+        &staker_nft,
+        VAULT_ID,
+        0,
+        1,
+        RewardMode::Periodic,
+    );
+    assert_staking_error(process(&mut context, instruction, &[]).await, StakingError::InvalidRewardMint);
+}
+
+#[tokio::test]
+async fn whitelist_by_non_admin_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+
+    let instruction = instruction::add_to_whitelist(
+        &fixture.program_id,
+        &fixture.staker.pubkey(),
+        VAULT_ID,
+        &fixture.whitelist_key,
+        PRICE,
+        VerificationMode::FirstCreator,
+    );
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::Unauthorized,
+    );
+}
+
+#[tokio::test]
+async fn withdraw_by_non_admin_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+
+    let instruction = instruction::withdraw(
+        &fixture.program_id,
+        &fixture.staker.pubkey(),
+        VAULT_ID,
+        &fixture.reward_mint,
+        1,
+    );
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::Unauthorized,
+    );
+}
+
+#[tokio::test]
+async fn withdraw_with_wrong_reward_mint_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+
+    let instruction = instruction::withdraw(
+        &fixture.program_id,
+        &context.payer.pubkey(),
+        VAULT_ID,
+        &fixture.nft_mint,
+        1,
+    );
+    assert_staking_error(process(&mut context, instruction, &[]).await, StakingError::WrongRewardMint);
+}
+
+#[tokio::test]
+async fn withdraw_by_admin_moves_rewards() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+
+    let admin = context.payer.pubkey();
+    let instruction = instruction::withdraw(&fixture.program_id, &admin, VAULT_ID, &fixture.reward_mint, 100);
+    process(&mut context, instruction, &[]).await.unwrap();
+
+    let admin_reward = get_associated_token_address(&admin, &fixture.reward_mint);
+    assert_eq!(token_balance(&mut context, &admin_reward).await, 100);
+}
+
+#[tokio::test]
+async fn stake_without_whitelist_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+
+    assert_staking_error(
+        process(&mut context, stake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::NotWhitelisted,
+    );
+}
+
+#[tokio::test]
+async fn stake_with_unverified_creator_fails() {
+    let (mut context, fixture) = setup(false).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    assert_staking_error(
+        process(&mut context, stake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::CreatorNotVerified,
+    );
+}
+
+#[tokio::test]
+async fn stake_with_wrong_metadata_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    let mut instruction = stake_instruction(&fixture);
+    instruction.accounts[2].pubkey = find_metadata_address(&fixture.reward_mint);
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::WrongMetadata,
+    );
+}
+
+#[tokio::test]
+async fn stake_with_wrong_token_program_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    let mut instruction = stake_instruction(&fixture);
+    instruction.accounts[6].pubkey = spl_associated_token_account::id();
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::WrongTokenProgram,
+    );
+}
+
+#[tokio::test]
+async fn stake_with_wrong_nft_holder_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    let mut instruction = stake_instruction(&fixture);
+    instruction.accounts[4].pubkey = Pubkey::new_unique();
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::WrongPayerNftHolder,
+    );
+}
+
+#[tokio::test]
+async fn stake_into_paused_vault_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    let instruction = instruction::set_paused(&fixture.program_id, &context.payer.pubkey(), VAULT_ID, true);
+    process(&mut context, instruction, &[]).await.unwrap();
+
+    assert_staking_error(
+        process(&mut context, stake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::VaultPaused,
+    );
+}
+
+#[tokio::test]
+async fn stake_into_disabled_whitelist_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    let instruction = instruction::disable_whitelist(
+        &fixture.program_id,
+        &context.payer.pubkey(),
+        VAULT_ID,
+        &fixture.whitelist_key,
+        true,
+    );
+    process(&mut context, instruction, &[]).await.unwrap();
+
+    assert_staking_error(
+        process(&mut context, stake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::WhitelistDisabled,
+    );
+}
+
+#[tokio::test]
+async fn unstake_before_min_period_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 86_400).await;
+    whitelist(&mut context, &fixture).await;
+    stake(&mut context, &fixture).await;
+
+    assert_staking_error(
+        process(&mut context, unstake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::MinPeriodNotReached,
+    );
+}
+
+fn emergency_unstake_instruction(fixture: &Fixture) -> Instruction {
+    instruction::emergency_unstake(&fixture.program_id, &fixture.staker.pubkey(), &fixture.nft_mint, VAULT_ID)
+}
+
+#[tokio::test]
+async fn emergency_unstake_before_min_period_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 86_400).await;
+    whitelist(&mut context, &fixture).await;
+    stake(&mut context, &fixture).await;
+
+    assert_staking_error(
+        process(&mut context, emergency_unstake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::MinPeriodNotReached,
+    );
+}
+
+#[tokio::test]
+async fn emergency_unstake_from_paused_vault_skips_min_period() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 86_400).await;
+    whitelist(&mut context, &fixture).await;
+    stake(&mut context, &fixture).await;
+    let instruction = instruction::set_paused(&fixture.program_id, &context.payer.pubkey(), VAULT_ID, true);
+    process(&mut context, instruction, &[]).await.unwrap();
+
+    process(&mut context, emergency_unstake_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+
+    let staker_nft = get_associated_token_address(&fixture.staker.pubkey(), &fixture.nft_mint);
+    assert_eq!(token_balance(&mut context, &staker_nft).await, 1);
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &fixture.reward_mint);
+    assert_eq!(token_balance(&mut context, &staker_reward).await, 0);
+    let (stake_address, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    assert!(!StakeData::try_from_slice(&stake_account.data).unwrap().active);
+
+    let (vault, _) = find_vault_address(&fixture.program_id, VAULT_ID);
+    let (stats_address, _) = find_stats_address(&fixture.program_id, &vault);
+    let stats_account = context.banks_client.get_account(stats_address).await.unwrap().unwrap();
+    assert_eq!(VaultStats::try_from_slice(&stats_account.data).unwrap().total_staked, 0);
+    let (staker_stats_address, _) = find_staker_stats_address(&fixture.program_id, &vault, &fixture.staker.pubkey());
+    let staker_stats_account = context.banks_client.get_account(staker_stats_address).await.unwrap().unwrap();
+    assert_eq!(StakerStats::try_from_slice(&staker_stats_account.data).unwrap().active_stakes, 0);
+}
+
+#[tokio::test]
+async fn stake_and_unstake_without_staker_stats() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    let mut instruction = stake_instruction(&fixture);
+    instruction.accounts.pop();
+    process(&mut context, instruction, &[&fixture.staker]).await.unwrap();
+
+    let (vault, _) = find_vault_address(&fixture.program_id, VAULT_ID);
+    let (stats_address, _) = find_stats_address(&fixture.program_id, &vault);
+    let stats_account = context.banks_client.get_account(stats_address).await.unwrap().unwrap();
+    let stats = VaultStats::try_from_slice(&stats_account.data).unwrap();
+    assert_eq!(stats.total_staked, 1);
+
+    context.warp_to_slot(1_000).unwrap();
+    let mut instruction = unstake_instruction(&fixture);
+    instruction.accounts.pop();
+    process(&mut context, instruction, &[&fixture.staker]).await.unwrap();
+
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &fixture.reward_mint);
+    let reward = token_balance(&mut context, &staker_reward).await;
+    assert!(reward > 0);
+    let stats_account = context.banks_client.get_account(stats_address).await.unwrap().unwrap();
+    let stats = VaultStats::try_from_slice(&stats_account.data).unwrap();
+    assert_eq!(stats.total_staked, 0);
+    assert_eq!(stats.total_rewards, reward);
+
+    let (staker_stats_address, _) =
+        find_staker_stats_address(&fixture.program_id, &vault, &fixture.staker.pubkey());
+    assert!(context.banks_client.get_account(staker_stats_address).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn unstake_twice_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    stake(&mut context, &fixture).await;
+    process(&mut context, unstake_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+
+    context.warp_to_slot(100).unwrap();
+    assert_staking_error(
+        process(&mut context, unstake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::StakeInactive,
+    );
+}
+
+#[tokio::test]
+async fn unstake_by_other_wallet_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    stake(&mut context, &fixture).await;
+
+    let instruction = instruction::unstake(
+        &fixture.program_id,
+        &context.payer.pubkey(),
+        &fixture.nft_mint,
+        VAULT_ID,
+        &fixture.whitelist_key,
+        &fixture.reward_mint,
+    );
+    assert_staking_error(process(&mut context, instruction, &[]).await, StakingError::Unauthorized);
+}
+
+#[tokio::test]
+async fn unstake_with_wrong_vault_reward_holder_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    stake(&mut context, &fixture).await;
+
+    let mut instruction = unstake_instruction(&fixture);
+    instruction.accounts[9].pubkey = Pubkey::new_unique();
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::WrongVaultRewardHolder,
+    );
+}
+
+#[tokio::test]
+async fn unstake_with_wrong_stake_account_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    stake(&mut context, &fixture).await;
+
+    let mut instruction = unstake_instruction(&fixture);
+    instruction.accounts[6].pubkey = find_stake_address(&fixture.program_id, &fixture.reward_mint).0;
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::WrongStakeData,
+    );
+}
+
+#[tokio::test]
+async fn accept_admin_without_proposal_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+
+    let instruction = instruction::accept_admin(&fixture.program_id, &fixture.staker.pubkey(), VAULT_ID);
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::NoPendingAdmin,
+    );
+}
+
+#[tokio::test]
+async fn admin_handover_is_two_step() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+
+    let admin = context.payer.pubkey();
+    let new_admin = fixture.staker.pubkey();
+    let instruction = instruction::propose_admin(&fixture.program_id, &admin, VAULT_ID, &new_admin);
+    process(&mut context, instruction, &[]).await.unwrap();
+
+    // the old admin stays in charge until the proposal is accepted
+    let instruction = instruction::set_paused(&fixture.program_id, &new_admin, VAULT_ID, true);
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::Unauthorized,
+    );
+
+    let instruction = instruction::accept_admin(&fixture.program_id, &new_admin, VAULT_ID);
+    process(&mut context, instruction, &[&fixture.staker]).await.unwrap();
+
+    // a new blockhash, the rejected transaction would otherwise be answered from the status cache
+    context.warp_to_slot(100).unwrap();
+    let instruction = instruction::set_paused(&fixture.program_id, &new_admin, VAULT_ID, true);
+    process(&mut context, instruction, &[&fixture.staker]).await.unwrap();
+}
+
+#[tokio::test]
+async fn set_admin_only_proposes() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+
+    let admin = context.payer.pubkey();
+    let new_admin = fixture.staker.pubkey();
+    let mut instruction = instruction::propose_admin(&fixture.program_id, &admin, VAULT_ID, &new_admin);
+    instruction.data = StakeInstruction::SetAdmin { new_admin }.try_to_vec().unwrap();
+    process(&mut context, instruction, &[]).await.unwrap();
+
+    let (vault, _) = find_vault_address(&fixture.program_id, VAULT_ID);
+    let vault_account = context.banks_client.get_account(vault).await.unwrap().unwrap();
+    let vault_data = ContractData::try_from_slice(&vault_account.data).unwrap();
+    assert_eq!(vault_data.admin, admin);
+    assert_eq!(vault_data.pending_admin, new_admin);
+}
+
+#[tokio::test]
+async fn malformed_instruction_data_fails() {
+    let (mut context, fixture) = setup(true).await;
+    let instruction = Instruction::new_with_bytes(fixture.program_id, &[0xff], vec![]);
+    assert_staking_error(process(&mut context, instruction, &[]).await, StakingError::InvalidInstruction);
+}
+
+#[tokio::test]
+async fn instruction_with_trailing_bytes_fails() {
+    let (mut context, fixture) = setup(true).await;
+    let mut instruction = instruction::generate_vault(
+        &fixture.program_id,
+        &context.payer.pubkey(),
+        &fixture.reward_mint,
+        VAULT_ID,
+        0,
+        1,
+        RewardMode::Periodic,
+    );
+    instruction.data.push(0);
+    assert_staking_error(process(&mut context, instruction, &[]).await, StakingError::InvalidInstruction);
+}
+
+#[tokio::test]
+async fn stake_nft_without_creators_fails() {
+    let (mut context, fixture) = setup_with_creators(|_| None).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    assert_staking_error(
+        process(&mut context, stake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::NoCreators,
+    );
+}
+
+#[tokio::test]
+async fn generate_vault_with_zero_reward_period_fails() {
+    let (mut context, fixture) = setup(true).await;
+    let instruction = instruction::generate_vault(
+        &fixture.program_id,
+        &context.payer.pubkey(),
+        &fixture.reward_mint,
+        VAULT_ID,
+        0,
+        0,
+        RewardMode::Periodic,
+    );
+    assert_staking_error(process(&mut context, instruction, &[]).await, StakingError::InvalidRewardPeriod);
+}
+
+#[test]
+fn legacy_vault_reads_in_place() {
+    let legacy_data = LegacyContractData {
+        min_period: 86_400,
+        reward_period: 3_600,
+    }
+    .try_to_vec()
+    .unwrap();
+    assert_eq!(
+        ContractData::unpack(&legacy_data).unwrap_err(),
+        StakingError::LegacyAccount
+    );
+
+    let vault_data = ContractData::from_legacy(&legacy_data).unwrap();
+    assert_eq!(vault_data.vault_id, LEGACY_VAULT_ID);
+    assert_eq!((vault_data.min_period, vault_data.reward_period), (86_400, 3_600));
+    assert_eq!(vault_data.admin, legacy_admin::id());
+    assert_eq!(vault_data.reward_mint, legacy_reward_mint::id());
+    assert_eq!(vault_data.reward_mode, RewardMode::Periodic);
+    assert!(!vault_data.early_exit);
+    assert_eq!(ContractData::unpack_legacy(&legacy_data).unwrap(), vault_data);
+    assert_eq!(ContractData::unpack(&vault_data.try_to_vec().unwrap()).unwrap(), vault_data);
+
+    // writes go back to the 16 byte layout as long as it can hold them
+    let mut vault_data = vault_data;
+    vault_data.min_period = 0;
+    assert_eq!(vault_data.to_legacy().unwrap().try_to_vec().unwrap().len(), ContractData::LEGACY_LEN);
+    vault_data.paused = true;
+    assert!(vault_data.to_legacy().is_none());
+
+    // the deployed program's vault PDA
+    let program_id = Pubkey::new_unique();
+    let (legacy_vault, _) = Pubkey::find_program_address(&[b"vault"], &program_id);
+    assert_eq!(find_vault_address(&program_id, LEGACY_VAULT_ID).0, legacy_vault);
+    let whitelist_key = Pubkey::new_unique();
+    assert_eq!(
+        find_whitelist_address(&program_id, &legacy_vault, &whitelist_key).0,
+        Pubkey::find_program_address(&[b"whitelist", whitelist_key.as_ref()], &program_id).0,
+    );
+}
+
+#[test]
+fn unknown_layout_version_fails() {
+    let vault_data = vec![AccountKey::Vault as u8, ContractData::VERSION + 1, 0];
+    assert_eq!(ContractData::unpack(&vault_data).unwrap_err(), StakingError::UnsupportedVersion);
+    let stake_data = vec![AccountKey::Stake as u8, StakeData::VERSION + 1, 0];
+    assert_eq!(StakeData::unpack(&stake_data).unwrap_err(), StakingError::UnsupportedVersion);
+}
+
+#[test]
+fn legacy_stake_needs_unstake_legacy() {
+    let legacy_data = LegacyStakeData {
+        timestamp: 1_640_000_000,
+        staker: Pubkey::new_unique(),
+        active: true,
+    }
+    .try_to_vec()
+    .unwrap();
+    assert_eq!(StakeData::unpack(&legacy_data).unwrap_err(), StakingError::LegacyAccount);
+}
+
+#[test]
+fn legacy_whitelist_reads_in_place() {
+    let legacy_data = LegacyRateData { price: PRICE }.try_to_vec().unwrap();
+    assert_eq!(RateData::unpack(&legacy_data).unwrap_err(), StakingError::LegacyAccount);
+
+    let rate_data = RateData::from_legacy(&legacy_data).unwrap();
+    assert_eq!(rate_data.price, PRICE);
+    assert!(!rate_data.disabled);
+    assert_eq!(rate_data.mode, VerificationMode::FirstCreator);
+    assert_eq!(RateData::unpack_legacy(&legacy_data).unwrap(), rate_data);
+    assert_eq!(RateData::unpack(&rate_data.try_to_vec().unwrap()).unwrap(), rate_data);
+
+    // repricing fits the 8 byte layout, disabling or verifying by collection does not
+    let mut rate_data = rate_data;
+    rate_data.price = 2 * PRICE;
+    assert_eq!(rate_data.to_legacy().unwrap(), LegacyRateData { price: 2 * PRICE });
+    rate_data.disabled = true;
+    assert!(rate_data.to_legacy().is_none());
+    rate_data.disabled = false;
+    rate_data.mode = VerificationMode::Collection;
+    assert!(rate_data.to_legacy().is_none());
+}
+
+// Nft staked with the deployed program into its vault 1_000 seconds before the test starts
+fn program_test_with_legacy_stake() -> (ProgramTest, Fixture) {
+    let (mut program_test, fixture) = program_test_with_verified_creator();
+    let program_id = fixture.program_id;
+    let (vault, _) = find_vault_address(&program_id, LEGACY_VAULT_ID);
+    let legacy_vault_data = LegacyContractData {
+        min_period: 0,
+        reward_period: 1,
+    };
+    program_test.add_account(vault, program_account(&program_id, legacy_vault_data.try_to_vec().unwrap()));
+    let (whitelist, _) = find_whitelist_address(&program_id, &vault, &fixture.whitelist_key);
+    let legacy_rate_data = LegacyRateData { price: PRICE };
+    program_test.add_account(whitelist, program_account(&program_id, legacy_rate_data.try_to_vec().unwrap()));
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let legacy_stake = LegacyStakeData {
+        timestamp: now - 1_000,
+        staker: fixture.staker.pubkey(),
+        active: true,
+    };
+    let (stake, _) = find_stake_address(&program_id, &fixture.nft_mint);
+    program_test.add_account(stake, program_account(&program_id, legacy_stake.try_to_vec().unwrap()));
+
+    let staker = fixture.staker.pubkey();
+    let nft_mint = fixture.nft_mint;
+    program_test.add_account(get_associated_token_address(&staker, &nft_mint), token_account(&nft_mint, &staker, 0));
+    program_test.add_account(get_associated_token_address(&vault, &nft_mint), token_account(&nft_mint, &vault, 1));
+    let reward_mint = legacy_reward_mint::id();
+    program_test.add_account(reward_mint, mint_account(VAULT_REWARDS, COption::None));
+    program_test.add_account(
+        get_associated_token_address(&vault, &reward_mint),
+        token_account(&reward_mint, &vault, VAULT_REWARDS),
+    );
+    (program_test, fixture)
+}
+
+#[tokio::test]
+async fn unstake_legacy_returns_nft_from_deployed_vault() {
+    let (program_test, fixture) = program_test_with_legacy_stake();
+    let mut context = program_test.start_with_context().await;
+    let (stake_address, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    let staked_at = LegacyStakeData::try_from_slice(&stake_account.data).unwrap().timestamp;
+
+    // the regular Unstake cannot read the 41 byte stake
+    let instruction = instruction::unstake(
+        &fixture.program_id,
+        &fixture.staker.pubkey(),
+        &fixture.nft_mint,
+        LEGACY_VAULT_ID,
+        &fixture.whitelist_key,
+        &legacy_reward_mint::id(),
+    );
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::LegacyAccount,
+    );
+
+    let instruction = instruction::unstake_legacy(
+        &fixture.program_id,
+        &fixture.staker.pubkey(),
+        &fixture.nft_mint,
+        &fixture.whitelist_key,
+    );
+    process(&mut context, instruction, &[&fixture.staker]).await.unwrap();
+
+    let clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    let expected_reward = (clock.unix_timestamp as u64 - staked_at) * PRICE;
+    let (vault, _) = find_vault_address(&fixture.program_id, LEGACY_VAULT_ID);
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &legacy_reward_mint::id());
+    let vault_reward = get_associated_token_address(&vault, &legacy_reward_mint::id());
+    assert_eq!(token_balance(&mut context, &staker_reward).await, expected_reward);
+    assert_eq!(token_balance(&mut context, &vault_reward).await, VAULT_REWARDS - expected_reward);
+    let staker_nft = get_associated_token_address(&fixture.staker.pubkey(), &fixture.nft_mint);
+    let vault_nft = get_associated_token_address(&vault, &fixture.nft_mint);
+    assert_eq!(token_balance(&mut context, &staker_nft).await, 1);
+    assert!(context.banks_client.get_account(vault_nft).await.unwrap().is_none());
+
+    // the 41 byte stake is closed so the nft can be staked again
+    assert!(context.banks_client.get_account(stake_address).await.unwrap().is_none());
+    let instruction = instruction::stake(
+        &fixture.program_id,
+        &fixture.staker.pubkey(),
+        &fixture.nft_mint,
+        LEGACY_VAULT_ID,
+        &fixture.whitelist_key,
+        None,
+    );
+    process(&mut context, instruction, &[&fixture.staker]).await.unwrap();
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    let stake_data = StakeData::unpack(&stake_account.data).unwrap();
+    assert!(stake_data.active);
+    assert_eq!(stake_data.vault, vault);
+    assert_eq!(token_balance(&mut context, &vault_nft).await, 1);
+}
+
+#[tokio::test]
+async fn legacy_vault_admin_paths_load_the_deployed_vault() {
+    let (program_test, fixture) = program_test_with_legacy_stake();
+    let mut context = program_test.start_with_context().await;
+    let program_id = fixture.program_id;
+    let staker = fixture.staker.pubkey();
+
+    // the admin is hardcoded, reaching the admin check shows the 16 byte vault and 8 byte whitelist load
+    let reward_mint = legacy_reward_mint::id();
+    let instructions = [
+        instruction::withdraw(&program_id, &staker, LEGACY_VAULT_ID, &reward_mint, 1),
+        instruction::set_paused(&program_id, &staker, LEGACY_VAULT_ID, true),
+        instruction::add_to_whitelist(
+            &program_id,
+            &staker,
+            LEGACY_VAULT_ID,
+            &fixture.whitelist_key,
+            PRICE,
+            VerificationMode::FirstCreator,
+        ),
+        instruction::remove_from_whitelist(&program_id, &staker, LEGACY_VAULT_ID, &fixture.whitelist_key),
+    ];
+    for instruction in instructions {
+        assert_staking_error(
+            process(&mut context, instruction, &[&fixture.staker]).await,
+            StakingError::Unauthorized,
+        );
+    }
+}
+
+#[tokio::test]
+async fn stake_by_verified_collection() {
+    let (mut context, fixture) = setup_with_collection(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist_with_mode(&mut context, &fixture, VerificationMode::Collection).await;
+    stake(&mut context, &fixture).await;
+
+    let (vault, _) = find_vault_address(&fixture.program_id, VAULT_ID);
+    let vault_nft = get_associated_token_address(&vault, &fixture.nft_mint);
+    assert_eq!(token_balance(&mut context, &vault_nft).await, 1);
+}
+
+#[tokio::test]
+async fn stake_with_unverified_collection_fails() {
+    let (mut context, fixture) = setup_with_collection(false).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist_with_mode(&mut context, &fixture, VerificationMode::Collection).await;
+
+    assert_staking_error(
+        process(&mut context, stake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::CollectionNotVerified,
+    );
+}
+
+#[tokio::test]
+async fn collection_nft_against_first_creator_whitelist_fails() {
+    let (mut context, fixture) = setup_with_collection(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist_with_mode(&mut context, &fixture, VerificationMode::FirstCreator).await;
+
+    assert_staking_error(
+        process(&mut context, stake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::WrongWhitelist,
+    );
+}
+
+#[tokio::test]
+async fn stake_fungible_mint_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    let instruction = instruction::stake(
+        &fixture.program_id,
+        &fixture.staker.pubkey(),
+        &fixture.reward_mint,
+        VAULT_ID,
+        &fixture.whitelist_key,
+        None,
+    );
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::NotAnNft,
+    );
+}
+
+#[tokio::test]
+async fn stake_with_wrong_master_edition_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    let mut instruction = stake_instruction(&fixture);
+    instruction.accounts[12].pubkey = find_metadata_address(&fixture.nft_mint);
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::WrongMasterEdition,
+    );
+}
+
+#[tokio::test]
+async fn stake_many_and_unstake_many_pay_rewards_once() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    let nfts = [
+        (fixture.nft_mint, fixture.whitelist_key),
+        (fixture.second_nft_mint, fixture.whitelist_key),
+    ];
+    let instruction = instruction::stake_many(&fixture.program_id, &fixture.staker.pubkey(), VAULT_ID, &nfts, None);
+    process(&mut context, instruction, &[&fixture.staker]).await.unwrap();
+
+    let (vault, _) = find_vault_address(&fixture.program_id, VAULT_ID);
+    let (stats_address, _) = find_stats_address(&fixture.program_id, &vault);
+    let stats_account = context.banks_client.get_account(stats_address).await.unwrap().unwrap();
+    let stats = VaultStats::try_from_slice(&stats_account.data).unwrap();
+    assert_eq!(stats.total_staked, 2);
+
+    let (stake_address, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    let staked_at = StakeData::try_from_slice(&stake_account.data).unwrap().timestamp;
+
+    context.warp_to_slot(1_000).unwrap();
+    let instruction = instruction::unstake_many(
+        &fixture.program_id,
+        &fixture.staker.pubkey(),
+        VAULT_ID,
+        &fixture.reward_mint,
+        &nfts,
+    );
+    process(&mut context, instruction, &[&fixture.staker]).await.unwrap();
+
+    let clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    let expected_reward = 2 * (clock.unix_timestamp as u64 - staked_at) * PRICE;
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &fixture.reward_mint);
+    assert_eq!(token_balance(&mut context, &staker_reward).await, expected_reward);
+    for (mint, _) in &nfts {
+        let staker_nft = get_associated_token_address(&fixture.staker.pubkey(), mint);
+        assert_eq!(token_balance(&mut context, &staker_nft).await, 1);
+    }
+
+    let stats_account = context.banks_client.get_account(stats_address).await.unwrap().unwrap();
+    let stats = VaultStats::try_from_slice(&stats_account.data).unwrap();
+    assert_eq!(stats.total_staked, 0);
+    assert_eq!(stats.total_rewards, expected_reward);
+}
+
+#[tokio::test]
+async fn stake_many_with_partial_nft_group_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    let mut instruction = instruction::stake_many(
+        &fixture.program_id,
+        &fixture.staker.pubkey(),
+        VAULT_ID,
+        &[(fixture.nft_mint, fixture.whitelist_key)],
+        None,
+    );
+    // drops the staker stats and the boost PDA, the last group is one account short
+    instruction.accounts.truncate(instruction.accounts.len() - 2);
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::InvalidNftAccounts,
+    );
+}
+
+#[tokio::test]
+async fn stake_in_wallet_with_wrong_metadata_program_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    let mut instruction = instruction::stake_in_wallet(
+        &fixture.program_id,
+        &fixture.staker.pubkey(),
+        &fixture.nft_mint,
+        VAULT_ID,
+        &fixture.whitelist_key,
+        None,
+    );
+    instruction.accounts[11].pubkey = spl_token::id();
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::WrongMetadataProgram,
+    );
+}
+
+async fn stake_in_wallet(context: &mut ProgramTestContext, fixture: &Fixture) {
+    let instruction = instruction::stake_in_wallet(
+        &fixture.program_id,
+        &fixture.staker.pubkey(),
+        &fixture.nft_mint,
+        VAULT_ID,
+        &fixture.whitelist_key,
+        None,
+    );
+    process(context, instruction, &[&fixture.staker]).await.unwrap();
+}
+
+async fn staker_nft_account(context: &mut ProgramTestContext, fixture: &Fixture) -> spl_token::state::Account {
+    let staker_nft = get_associated_token_address(&fixture.staker.pubkey(), &fixture.nft_mint);
+    let account = context.banks_client.get_account(staker_nft).await.unwrap().unwrap();
+    spl_token::state::Account::unpack(&account.data).unwrap()
+}
+
+// The nft is back in the staker's hands, neither frozen nor delegated
+async fn assert_wallet_nft_released(context: &mut ProgramTestContext, fixture: &Fixture) {
+    let nft_account = staker_nft_account(context, fixture).await;
+    assert_eq!(nft_account.amount, 1);
+    assert_eq!(nft_account.state, spl_token::state::AccountState::Initialized);
+    assert_eq!(nft_account.delegate, COption::None);
+    assert_eq!(nft_account.delegated_amount, 0);
+}
+
+#[tokio::test]
+async fn stake_in_wallet_freezes_and_unstake_thaws() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    stake_in_wallet(&mut context, &fixture).await;
+
+    let (vault, _) = find_vault_address(&fixture.program_id, VAULT_ID);
+    let nft_account = staker_nft_account(&mut context, &fixture).await;
+    assert_eq!(nft_account.amount, 1);
+    assert_eq!(nft_account.state, spl_token::state::AccountState::Frozen);
+    assert_eq!(nft_account.delegate, COption::Some(vault));
+    assert_eq!(nft_account.delegated_amount, 1);
+
+    let (stake_address, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    assert_eq!(StakeData::try_from_slice(&stake_account.data).unwrap().custody, CustodyMode::Wallet);
+
+    context.warp_to_slot(1_000).unwrap();
+    process(&mut context, unstake_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+
+    assert_wallet_nft_released(&mut context, &fixture).await;
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &fixture.reward_mint);
+    assert!(token_balance(&mut context, &staker_reward).await > 0);
+    let vault_nft = get_associated_token_address(&vault, &fixture.nft_mint);
+    assert!(context.banks_client.get_account(vault_nft).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn emergency_unstake_thaws_wallet_stake() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    stake_in_wallet(&mut context, &fixture).await;
+
+    process(&mut context, emergency_unstake_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+
+    assert_wallet_nft_released(&mut context, &fixture).await;
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &fixture.reward_mint);
+    assert_eq!(token_balance(&mut context, &staker_reward).await, 0);
+    let (stake_address, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    assert!(!StakeData::try_from_slice(&stake_account.data).unwrap().active);
+}
+
+// Tier 0 pays double without a lock, tier 1 locks for a long time at the plain price
+async fn set_lock_tiers(context: &mut ProgramTestContext, fixture: &Fixture) {
+    let mut lock_tiers = [LockTier::default(); MAX_LOCK_TIERS];
+    lock_tiers[0] = LockTier {
+        duration: 0,
+        multiplier_bps: 20_000,
+    };
+    lock_tiers[1] = LockTier {
+        duration: 1_000_000,
+        multiplier_bps: 10_000,
+    };
+    let instruction = instruction::set_lock_tiers(&fixture.program_id, &context.payer.pubkey(), VAULT_ID, lock_tiers);
+    process(context, instruction, &[]).await.unwrap();
+}
+
+fn locked_stake_instruction(fixture: &Fixture, lock_tier: u8) -> Instruction {
+    instruction::stake(
+        &fixture.program_id,
+        &fixture.staker.pubkey(),
+        &fixture.nft_mint,
+        VAULT_ID,
+        &fixture.whitelist_key,
+        Some(lock_tier),
+    )
+}
+
+#[tokio::test]
+async fn lock_tier_multiplier_scales_rewards() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    set_lock_tiers(&mut context, &fixture).await;
+    process(&mut context, locked_stake_instruction(&fixture, 0), &[&fixture.staker]).await.unwrap();
+
+    let (stake_address, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    let stake_data = StakeData::try_from_slice(&stake_account.data).unwrap();
+    assert_eq!(stake_data.multiplier_bps, 20_000);
+
+    context.warp_to_slot(1_000).unwrap();
+    process(&mut context, unstake_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+
+    let clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    let expected_reward = 2 * (clock.unix_timestamp as u64 - stake_data.timestamp) * PRICE;
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &fixture.reward_mint);
+    assert_eq!(token_balance(&mut context, &staker_reward).await, expected_reward);
+}
+
+#[tokio::test]
+async fn unstake_before_lock_end_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    set_lock_tiers(&mut context, &fixture).await;
+    process(&mut context, locked_stake_instruction(&fixture, 1), &[&fixture.staker]).await.unwrap();
+
+    assert_staking_error(
+        process(&mut context, unstake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::LockNotExpired,
+    );
+}
+
+#[tokio::test]
+async fn stake_with_unused_lock_tier_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    set_lock_tiers(&mut context, &fixture).await;
+
+    assert_staking_error(
+        process(&mut context, locked_stake_instruction(&fixture, 2), &[&fixture.staker]).await,
+        StakingError::InvalidLockTier,
+    );
+}
+
+#[tokio::test]
+async fn early_exit_withholds_penalty() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 1_000_000).await;
+    whitelist(&mut context, &fixture).await;
+    let instruction = instruction::set_early_exit(&fixture.program_id, &context.payer.pubkey(), VAULT_ID, true, 2_500);
+    process(&mut context, instruction, &[]).await.unwrap();
+    stake(&mut context, &fixture).await;
+
+    let (stake_address, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    let staked_at = StakeData::try_from_slice(&stake_account.data).unwrap().timestamp;
+
+    context.warp_to_slot(1_000).unwrap();
+    process(&mut context, unstake_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+
+    let clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    let full_reward = (clock.unix_timestamp as u64 - staked_at) * PRICE;
+    let expected_reward = full_reward - full_reward * 2_500 / 10_000;
+    let (vault, _) = find_vault_address(&fixture.program_id, VAULT_ID);
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &fixture.reward_mint);
+    let vault_reward = get_associated_token_address(&vault, &fixture.reward_mint);
+    assert_eq!(token_balance(&mut context, &staker_reward).await, expected_reward);
+    assert_eq!(token_balance(&mut context, &vault_reward).await, VAULT_REWARDS - expected_reward);
+}
+
+#[tokio::test]
+async fn claim_before_min_period_pays_without_early_exit() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 1_000_000).await;
+    whitelist(&mut context, &fixture).await;
+    stake(&mut context, &fixture).await;
+
+    context.warp_to_slot(1_000).unwrap();
+    process(&mut context, claim_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &fixture.reward_mint);
+    assert!(token_balance(&mut context, &staker_reward).await > 0);
+    let (stake_address, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    assert!(StakeData::try_from_slice(&stake_account.data).unwrap().active);
+}
+
+#[tokio::test]
+async fn claim_before_min_period_fails_and_early_exit_keeps_penalty() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 1_000_000).await;
+    whitelist(&mut context, &fixture).await;
+    let instruction = instruction::set_early_exit(&fixture.program_id, &context.payer.pubkey(), VAULT_ID, true, 2_500);
+    process(&mut context, instruction, &[]).await.unwrap();
+    stake(&mut context, &fixture).await;
+
+    let (stake_address, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    let staked_at = StakeData::try_from_slice(&stake_account.data).unwrap().timestamp;
+
+    context.warp_to_slot(1_000).unwrap();
+    assert_staking_error(
+        process(&mut context, claim_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::MinPeriodNotReached,
+    );
+    process(&mut context, unstake_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+
+    // the penalty applies to everything accrued since the stake
+    let clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    let full_reward = (clock.unix_timestamp as u64 - staked_at) * PRICE;
+    let expected_reward = full_reward - full_reward * 2_500 / 10_000;
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &fixture.reward_mint);
+    assert_eq!(token_balance(&mut context, &staker_reward).await, expected_reward);
+}
+
+#[tokio::test]
+async fn early_exit_penalty_above_full_reward_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    let instruction = instruction::set_early_exit(&fixture.program_id, &context.payer.pubkey(), VAULT_ID, true, 10_001);
+    assert_staking_error(process(&mut context, instruction, &[]).await, StakingError::InvalidPenalty);
+}
+
+#[tokio::test]
+async fn boost_multiplies_rewards() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    let instruction = instruction::set_boosts(
+        &fixture.program_id,
+        &context.payer.pubkey(),
+        VAULT_ID,
+        &[(fixture.nft_mint, 30_000), (fixture.second_nft_mint, 15_000)],
+    );
+    process(&mut context, instruction, &[]).await.unwrap();
+    stake(&mut context, &fixture).await;
+
+    let (stake_address, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    let stake_data = StakeData::try_from_slice(&stake_account.data).unwrap();
+    assert_eq!(stake_data.boost_bps, 30_000);
+
+    context.warp_to_slot(1_000).unwrap();
+    process(&mut context, unstake_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+
+    let clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    let expected_reward = 3 * (clock.unix_timestamp as u64 - stake_data.timestamp) * PRICE;
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &fixture.reward_mint);
+    assert_eq!(token_balance(&mut context, &staker_reward).await, expected_reward);
+}
+
+#[tokio::test]
+async fn boost_set_after_stake_applies_from_the_next_claim() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    stake(&mut context, &fixture).await;
+    let instruction =
+        instruction::set_boosts(&fixture.program_id, &context.payer.pubkey(), VAULT_ID, &[(fixture.nft_mint, 30_000)]);
+    process(&mut context, instruction, &[]).await.unwrap();
+
+    let (stake_address, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    let stake_data = |account: Option<Account>| StakeData::try_from_slice(&account.unwrap().data).unwrap();
+    let staked_at = stake_data(context.banks_client.get_account(stake_address).await.unwrap()).timestamp;
+
+    // without the boost PDA the stake keeps the boost it was staked with
+    context.warp_to_slot(1_000).unwrap();
+    let mut instruction = claim_instruction(&fixture);
+    instruction.accounts.truncate(instruction.accounts.len() - 2);
+    process(&mut context, instruction, &[&fixture.staker]).await.unwrap();
+    let first_claim = stake_data(context.banks_client.get_account(stake_address).await.unwrap());
+    assert_eq!(first_claim.boost_bps, 10_000);
+
+    // with it the stored boost pays up to now and the new one from here on
+    context.warp_to_slot(2_000).unwrap();
+    process(&mut context, claim_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+    let second_claim = stake_data(context.banks_client.get_account(stake_address).await.unwrap());
+    assert_eq!(second_claim.boost_bps, 30_000);
+
+    context.warp_to_slot(3_000).unwrap();
+    process(&mut context, unstake_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+    let unstaked = stake_data(context.banks_client.get_account(stake_address).await.unwrap());
+
+    let expected_reward = (second_claim.claimed_at - staked_at) * PRICE
+        + (unstaked.claimed_at - second_claim.claimed_at) * PRICE * 3;
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &fixture.reward_mint);
+    assert_eq!(token_balance(&mut context, &staker_reward).await, expected_reward);
+}
+
+#[tokio::test]
+async fn set_boosts_out_of_range_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+
+    let admin = context.payer.pubkey();
+    let instruction = instruction::set_boosts(&fixture.program_id, &admin, VAULT_ID, &[(fixture.nft_mint, 0)]);
+    assert_staking_error(process(&mut context, instruction, &[]).await, StakingError::InvalidBoost);
+    let instruction =
+        instruction::set_boosts(&fixture.program_id, &admin, VAULT_ID, &[(fixture.nft_mint, MAX_BOOST_BPS + 1)]);
+    assert_staking_error(process(&mut context, instruction, &[]).await, StakingError::InvalidBoost);
+}
+
+#[tokio::test]
+async fn set_boosts_by_non_admin_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+
+    let instruction = instruction::set_boosts(
+        &fixture.program_id,
+        &fixture.staker.pubkey(),
+        VAULT_ID,
+        &[(fixture.nft_mint, 30_000)],
+    );
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::Unauthorized,
+    );
+}
+
+#[tokio::test]
+async fn switch_to_periodic_keeps_reward_carry() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    let admin = context.payer.pubkey();
+    let instruction = instruction::update_vault_config(&fixture.program_id, &admin, VAULT_ID, 0, 3, RewardMode::Linear);
+    process(&mut context, instruction, &[]).await.unwrap();
+    stake(&mut context, &fixture).await;
+
+    // a third of PRICE per second leaves a fraction of a token behind
+    context.warp_to_slot(1_000).unwrap();
+    process(&mut context, claim_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+    let instruction =
+        instruction::update_vault_config(&fixture.program_id, &admin, VAULT_ID, 0, 1, RewardMode::Periodic);
+    process(&mut context, instruction, &[]).await.unwrap();
+
+    let (stake_address, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    let claimed = StakeData::try_from_slice(&stake_account.data).unwrap();
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &fixture.reward_mint);
+    let claimed_reward = token_balance(&mut context, &staker_reward).await;
+
+    context.warp_to_slot(2_000).unwrap();
+    process(&mut context, unstake_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+
+    let clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    let expected_reward = (clock.unix_timestamp as u64 - claimed.claimed_at) * PRICE;
+    assert_eq!(token_balance(&mut context, &staker_reward).await, claimed_reward + expected_reward);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    assert_eq!(StakeData::try_from_slice(&stake_account.data).unwrap().reward_carry, claimed.reward_carry);
+}
+
+#[tokio::test]
+async fn stake_into_corrupt_vault_fails() {
+    let (mut program_test, fixture) = program_test_with_verified_creator();
+    let (vault, _) = find_vault_address(&fixture.program_id, VAULT_ID);
+    program_test.add_account(vault, truncated_account(&fixture.program_id, AccountKey::Vault, ContractData::VERSION));
+    let mut context = program_test.start_with_context().await;
+
+    assert_staking_error(
+        process(&mut context, stake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::InvalidVaultData,
+    );
+}
+
+#[tokio::test]
+async fn stake_with_corrupt_whitelist_fails() {
+    let (mut program_test, fixture) = program_test_with_verified_creator();
+    let (vault, _) = find_vault_address(&fixture.program_id, VAULT_ID);
+    let (whitelist, _) = find_whitelist_address(&fixture.program_id, &vault, &fixture.whitelist_key);
+    let whitelist_account = truncated_account(&fixture.program_id, AccountKey::Whitelist, RateData::VERSION);
+    program_test.add_account(whitelist, whitelist_account);
+    let mut context = program_test.start_with_context().await;
+    generate_vault(&mut context, &fixture, 0).await;
+
+    assert_staking_error(
+        process(&mut context, stake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::InvalidRateData,
+    );
+}
+
+#[tokio::test]
+async fn unstake_with_corrupt_stake_fails() {
+    let (mut program_test, fixture) = program_test_with_verified_creator();
+    let (stake, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    program_test.add_account(stake, truncated_account(&fixture.program_id, AccountKey::Stake, StakeData::VERSION));
+    let mut context = program_test.start_with_context().await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    assert_staking_error(
+        process(&mut context, unstake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::InvalidStakeData,
+    );
+}
+
+#[tokio::test]
+async fn stake_with_corrupt_stats_fails() {
+    let (mut program_test, fixture) = program_test_with_verified_creator();
+    let (vault, _) = find_vault_address(&fixture.program_id, VAULT_ID);
+    let (stats, _) = find_stats_address(&fixture.program_id, &vault);
+    program_test.add_account(stats, truncated_account(&fixture.program_id, AccountKey::Stats, VaultStats::VERSION));
+    let mut context = program_test.start_with_context().await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    assert_staking_error(
+        process(&mut context, stake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::InvalidStatsData,
+    );
+}
+
+#[tokio::test]
+async fn stake_with_stats_of_another_account_type_fails() {
+    let (mut program_test, fixture) = program_test_with_verified_creator();
+    let (vault, _) = find_vault_address(&fixture.program_id, VAULT_ID);
+    let (stats, _) = find_stats_address(&fixture.program_id, &vault);
+    program_test.add_account(stats, truncated_account(&fixture.program_id, AccountKey::Vault, ContractData::VERSION));
+    let mut context = program_test.start_with_context().await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    assert_staking_error(
+        process(&mut context, stake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::WrongAccountType,
+    );
+}
+
+#[tokio::test]
+async fn stake_with_corrupt_boost_fails() {
+    let (mut program_test, fixture) = program_test_with_verified_creator();
+    let (vault, _) = find_vault_address(&fixture.program_id, VAULT_ID);
+    let (boost, _) = find_boost_address(&fixture.program_id, &vault, &fixture.nft_mint);
+    program_test.add_account(boost, truncated_account(&fixture.program_id, AccountKey::Boost, BoostData::VERSION));
+    let mut context = program_test.start_with_context().await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    assert_staking_error(
+        process(&mut context, stake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::InvalidBoostData,
+    );
+}
+
+#[tokio::test]
+async fn stake_with_invalid_metadata_fails() {
+    let (mut program_test, fixture) = program_test_with_verified_creator();
+    let metadata = program_account(&spl_token_metadata::id(), vec![0; 10]);
+    program_test.add_account(find_metadata_address(&fixture.nft_mint), metadata);
+    let mut context = program_test.start_with_context().await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    assert_staking_error(
+        process(&mut context, stake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::InvalidMetadata,
+    );
+}
+
+#[tokio::test]
+async fn stake_with_mint_of_another_program_fails() {
+    let (mut program_test, fixture) = program_test_with_verified_creator();
+    program_test.add_account(
+        fixture.nft_mint,
+        Account {
+            owner: Pubkey::new_unique(),
+            ..mint_account(1, COption::None)
+        },
+    );
+    let mut context = program_test.start_with_context().await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    assert_staking_error(
+        process(&mut context, stake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::InvalidMint,
+    );
+}
+
+#[tokio::test]
+async fn stake_with_wrong_vault_nft_holder_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    let mut instruction = stake_instruction(&fixture);
+    instruction.accounts[5].pubkey = Pubkey::new_unique();
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::WrongVaultNftHolder,
+    );
+}
+
+#[tokio::test]
+async fn stake_with_wrong_stats_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    let mut instruction = stake_instruction(&fixture);
+    instruction.accounts[13].pubkey = Pubkey::new_unique();
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::WrongStats,
+    );
+}
+
+#[tokio::test]
+async fn stake_with_wrong_boost_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    // the boost PDA of another mint
+    let (vault, _) = find_vault_address(&fixture.program_id, VAULT_ID);
+    let mut instruction = stake_instruction(&fixture);
+    instruction.accounts[14].pubkey = find_boost_address(&fixture.program_id, &vault, &fixture.second_nft_mint).0;
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::WrongBoost,
+    );
+}
+
+#[tokio::test]
+async fn stake_with_wrong_staker_stats_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    let mut instruction = stake_instruction(&fixture);
+    instruction.accounts[15].pubkey = Pubkey::new_unique();
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::WrongStakerStats,
+    );
+}
+
+#[tokio::test]
+async fn stake_with_overflowing_lock_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    let mut lock_tiers = [LockTier::default(); MAX_LOCK_TIERS];
+    lock_tiers[0] = LockTier {
+        duration: u64::MAX,
+        multiplier_bps: 10_000,
+    };
+    let instruction = instruction::set_lock_tiers(&fixture.program_id, &context.payer.pubkey(), VAULT_ID, lock_tiers);
+    process(&mut context, instruction, &[]).await.unwrap();
+
+    assert_staking_error(
+        process(&mut context, locked_stake_instruction(&fixture, 0), &[&fixture.staker]).await,
+        StakingError::MathOverflow,
+    );
+}
+
+#[tokio::test]
+async fn unstake_with_wrong_staker_reward_holder_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    stake(&mut context, &fixture).await;
+
+    let mut instruction = unstake_instruction(&fixture);
+    instruction.accounts[8].pubkey = Pubkey::new_unique();
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::WrongPayerRewardHolder,
+    );
+}
+
+#[tokio::test]
+async fn unstake_from_another_vault_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    stake(&mut context, &fixture).await;
+
+    let other_vault_id = VAULT_ID + 1;
+    let instruction = instruction::generate_vault(
+        &fixture.program_id,
+        &context.payer.pubkey(),
+        &fixture.reward_mint,
+        other_vault_id,
+        0,
+        1,
+        RewardMode::Periodic,
+    );
+    process(&mut context, instruction, &[]).await.unwrap();
+
+    let instruction = instruction::unstake(
+        &fixture.program_id,
+        &fixture.staker.pubkey(),
+        &fixture.nft_mint,
+        other_vault_id,
+        &fixture.whitelist_key,
+        &fixture.reward_mint,
+    );
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::WrongStakeVault,
+    );
+}
+
+#[tokio::test]
+async fn unstake_from_paused_vault_skips_rewards() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 86_400).await;
+    whitelist(&mut context, &fixture).await;
+    stake(&mut context, &fixture).await;
+    context.warp_to_slot(1_000).unwrap();
+    let instruction = instruction::set_paused(&fixture.program_id, &context.payer.pubkey(), VAULT_ID, true);
+    process(&mut context, instruction, &[]).await.unwrap();
+
+    // min_period does not hold either
+    process(&mut context, unstake_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+
+    let staker_nft = get_associated_token_address(&fixture.staker.pubkey(), &fixture.nft_mint);
+    assert_eq!(token_balance(&mut context, &staker_nft).await, 1);
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &fixture.reward_mint);
+    assert_eq!(token_balance(&mut context, &staker_reward).await, 0);
+    let (stake_address, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    assert!(!StakeData::try_from_slice(&stake_account.data).unwrap().active);
+}
+
+#[tokio::test]
+async fn claim_pays_and_keeps_nft_staked() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    stake(&mut context, &fixture).await;
+
+    let (stake_address, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    let staked_at = StakeData::try_from_slice(&stake_account.data).unwrap().timestamp;
+
+    context.warp_to_slot(1_000).unwrap();
+    process(&mut context, claim_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+
+    let clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &fixture.reward_mint);
+    let claimed_reward = (clock.unix_timestamp as u64 - staked_at) * PRICE;
+    assert_eq!(token_balance(&mut context, &staker_reward).await, claimed_reward);
+
+    let (vault, _) = find_vault_address(&fixture.program_id, VAULT_ID);
+    let vault_nft = get_associated_token_address(&vault, &fixture.nft_mint);
+    assert_eq!(token_balance(&mut context, &vault_nft).await, 1);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    let stake_data = StakeData::try_from_slice(&stake_account.data).unwrap();
+    assert!(stake_data.active);
+    assert_eq!(stake_data.claimed_at, clock.unix_timestamp as u64);
+
+    // the next claim only pays what accrued since the last one
+    context.warp_to_slot(2_000).unwrap();
+    process(&mut context, claim_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+    let clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    let expected_reward = (clock.unix_timestamp as u64 - staked_at) * PRICE;
+    assert_eq!(token_balance(&mut context, &staker_reward).await, expected_reward);
+}
+
+#[tokio::test]
+async fn claim_from_paused_vault_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    stake(&mut context, &fixture).await;
+    let instruction = instruction::set_paused(&fixture.program_id, &context.payer.pubkey(), VAULT_ID, true);
+    process(&mut context, instruction, &[]).await.unwrap();
+
+    assert_staking_error(
+        process(&mut context, claim_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::VaultPaused,
+    );
+}
+
+#[tokio::test]
+async fn linear_mode_pays_between_periods() {
+    let (mut context, fixture) = setup(true).await;
+    let instruction = instruction::generate_vault(
+        &fixture.program_id,
+        &context.payer.pubkey(),
+        &fixture.reward_mint,
+        VAULT_ID,
+        0,
+        7,
+        RewardMode::Linear,
+    );
+    process(&mut context, instruction, &[]).await.unwrap();
+    whitelist(&mut context, &fixture).await;
+    stake(&mut context, &fixture).await;
+
+    let (stake_address, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    let staked_at = StakeData::try_from_slice(&stake_account.data).unwrap().timestamp;
+
+    context.warp_to_slot(1_000).unwrap();
+    process(&mut context, unstake_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+
+    // PRICE is spread over the 7 seconds of a period instead of paid once it is over
+    let clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    let expected_reward = (clock.unix_timestamp as u64 - staked_at) * PRICE / 7;
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &fixture.reward_mint);
+    assert_eq!(token_balance(&mut context, &staker_reward).await, expected_reward);
+}
+
+#[tokio::test]
+async fn update_vault_config_settles_running_stakes_at_the_old_rate() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+    stake(&mut context, &fixture).await;
+
+    let (stake_address, _) = find_stake_address(&fixture.program_id, &fixture.nft_mint);
+    let stake_account = context.banks_client.get_account(stake_address).await.unwrap().unwrap();
+    let staked_at = StakeData::try_from_slice(&stake_account.data).unwrap().timestamp;
+
+    context.warp_to_slot(1_000).unwrap();
+    let admin = context.payer.pubkey();
+    let instruction =
+        instruction::update_vault_config(&fixture.program_id, &admin, VAULT_ID, 0, 2, RewardMode::Periodic);
+    process(&mut context, instruction, &[]).await.unwrap();
+    let updated_at = context.banks_client.get_sysvar::<Clock>().await.unwrap().unix_timestamp as u64;
+
+    context.warp_to_slot(2_000).unwrap();
+    process(&mut context, unstake_instruction(&fixture), &[&fixture.staker]).await.unwrap();
+
+    // a period per second up to the update, one every two seconds after it
+    let clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+    let periods = (updated_at - staked_at) + (clock.unix_timestamp as u64 - updated_at) / 2;
+    let staker_reward = get_associated_token_address(&fixture.staker.pubkey(), &fixture.reward_mint);
+    assert_eq!(token_balance(&mut context, &staker_reward).await, periods * PRICE);
+}
+
+#[tokio::test]
+async fn remove_from_whitelist_closes_it() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    let admin = context.payer.pubkey();
+    let (vault, _) = find_vault_address(&fixture.program_id, VAULT_ID);
+    let (whitelist_address, _) = find_whitelist_address(&fixture.program_id, &vault, &fixture.whitelist_key);
+    let whitelist_account = context.banks_client.get_account(whitelist_address).await.unwrap().unwrap();
+    let admin_balance = context.banks_client.get_balance(admin).await.unwrap();
+    let (fee_calculator, _, _) = context.banks_client.get_fees().await.unwrap();
+
+    let instruction = instruction::remove_from_whitelist(&fixture.program_id, &admin, VAULT_ID, &fixture.whitelist_key);
+    process(&mut context, instruction, &[]).await.unwrap();
+
+    // the rent goes back to the admin, who also paid the fee
+    assert!(context.banks_client.get_account(whitelist_address).await.unwrap().is_none());
+    assert_eq!(
+        context.banks_client.get_balance(admin).await.unwrap(),
+        admin_balance + whitelist_account.lamports - fee_calculator.lamports_per_signature,
+    );
+    assert_staking_error(
+        process(&mut context, stake_instruction(&fixture), &[&fixture.staker]).await,
+        StakingError::NotWhitelisted,
+    );
+}
+
+#[tokio::test]
+async fn remove_from_whitelist_by_non_admin_fails() {
+    let (mut context, fixture) = setup(true).await;
+    generate_vault(&mut context, &fixture, 0).await;
+    whitelist(&mut context, &fixture).await;
+
+    let instruction = instruction::remove_from_whitelist(
+        &fixture.program_id,
+        &fixture.staker.pubkey(),
+        VAULT_ID,
+        &fixture.whitelist_key,
+    );
+    assert_staking_error(
+        process(&mut context, instruction, &[&fixture.staker]).await,
+        StakingError::Unauthorized,
+    );
+}